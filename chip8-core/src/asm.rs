@@ -0,0 +1,626 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::opcodes::{self, OpcodeInfo, OPCODES};
+
+// limit on nested macro expansion, to stop a macro that invokes itself
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// A problem in assembler source, with the line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    text: String,
+    // line reported in errors
+    line: usize,
+    // line the token sits on for keeping instructions to one line; each
+    // expansion of a macro gets rows of its own, one per body line
+    row: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// An address operand, which may name a label defined further on.
+#[derive(Debug, Clone)]
+enum Address {
+    Known(u32),
+    Label(String),
+}
+
+#[derive(Debug, Default)]
+struct Operands {
+    x: u16,
+    y: u16,
+    n: u16,
+    kk: u16,
+    nnn: Option<Address>,
+    long: Option<Address>,
+    far: Option<Address>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    // low 12 bits of the opcode at the offset
+    Nnn,
+    // the whole word at the offset
+    Long,
+    // low byte of the opcode at the offset and the word after it
+    Far,
+}
+
+struct Fixup {
+    offset: usize,
+    kind: FixupKind,
+    label: String,
+    line: usize,
+}
+
+/// Assembles source into a ROM image to be loaded at `origin`.
+///
+/// Instructions may be written in either syntax of the opcode table the
+/// disassembler uses: Octo (`v1 := 0x20`, `sprite v0 v1 5`) or classic
+/// (`LD V1, 0x20`, `DRW V0, V1, 5`), so its output always assembles back to
+/// the same bytes. A line may hold several instructions, but an instruction
+/// can't continue onto the next line. Beyond instructions the source may
+/// contain:
+///
+/// - labels, as `: name` or `name:`, usable wherever an address is;
+///   a bare label name calls it
+/// - `:const name value`
+/// - data, as `:byte value`, `DB value, ...`, `DW value, ...` or bare numbers
+/// - `:macro name params... { body }`, expanded where `name` appears with
+///   its arguments substituted for the parameters
+///
+/// Comments run from `#` or `;` to the end of the line. Numbers are decimal,
+/// `0x` hex or `0b` binary.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        origin,
+        rom: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+    };
+    assembler.run(tokenize(source), source.lines().count())?;
+    assembler.resolve()?;
+    Ok(assembler.rom)
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = match line.find(['#', ';']) {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        for word in code.split(|c: char| c.is_whitespace() || c == ',') {
+            if !word.is_empty() {
+                tokens.push(Token {
+                    text: word.to_string(),
+                    line: index + 1,
+                    row: index + 1,
+                });
+            }
+        }
+    }
+    tokens
+}
+
+fn error<T>(line: usize, message: String) -> Result<T, AsmError> {
+    Err(AsmError { line, message })
+}
+
+struct Assembler {
+    origin: u16,
+    rom: Vec<u8>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, u16>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+}
+
+impl Assembler {
+    fn here(&self) -> u16 {
+        self.origin.wrapping_add(self.rom.len() as u16)
+    }
+
+    fn run(&mut self, mut tokens: Vec<Token>, mut rows: usize) -> Result<(), AsmError> {
+        let mut i = 0;
+        let mut expansions = 0;
+
+        while i < tokens.len() {
+            let token = tokens[i].clone();
+            let line = token.line;
+            let text = token.text.as_str();
+
+            if let Some(params) = self.macros.get(text).map(|m| m.params.len()) {
+                // splice the body in place of the invocation and carry on
+                // from its first token
+                expansions += 1;
+                if expansions > MAX_EXPANSIONS {
+                    return error(line, format!("macro '{}' expands forever", text));
+                }
+                if i + params >= tokens.len() {
+                    return error(
+                        line,
+                        format!("macro '{}' expects {} arguments", text, params),
+                    );
+                }
+                let args: HashMap<&str, &Token> = self.macros[text]
+                    .params
+                    .iter()
+                    .map(String::as_str)
+                    .zip(&tokens[i + 1..=i + params])
+                    .collect();
+                let body = &self.macros[text].body;
+                let first_row = body.first().map_or(0, |token| token.row);
+                let body: Vec<Token> = body
+                    .iter()
+                    .map(|token| Token {
+                        text: args.get(token.text.as_str()).unwrap_or(&token).text.clone(),
+                        line,
+                        row: rows + 1 + token.row - first_row,
+                    })
+                    .collect();
+                rows = body.last().map_or(rows, |token| token.row);
+                tokens.splice(i..=i + params, body);
+                continue;
+            }
+
+            match text {
+                ":" => {
+                    let name = operand(&tokens, i + 1, line)?;
+                    self.define_label(name, line)?;
+                    i += 2;
+                }
+                ":const" => {
+                    let name = operand(&tokens, i + 1, line)?;
+                    let value = operand(&tokens, i + 2, line)?;
+                    let value = match self.value(value) {
+                        Some(value) => value as u16,
+                        None => return error(line, format!("bad constant value '{}'", value)),
+                    };
+                    self.check_name(name, line)?;
+                    self.constants.insert(name.to_string(), value);
+                    i += 3;
+                }
+                ":byte" => {
+                    let value = operand(&tokens, i + 1, line)?;
+                    self.byte(value, line)?;
+                    i += 2;
+                }
+                ":macro" => i = self.define_macro(&tokens, i, line)?,
+                "DB" | "db" | "DW" | "dw" => {
+                    i += 1;
+                    while i < tokens.len() && self.value(&tokens[i].text).is_some() {
+                        if text.eq_ignore_ascii_case("dw") {
+                            let value = self.value(&tokens[i].text).unwrap() as u16;
+                            self.rom.extend_from_slice(&value.to_be_bytes());
+                        } else {
+                            self.byte(&tokens[i].text, line)?;
+                        }
+                        i += 1;
+                    }
+                }
+                _ if text.len() > 1 && text.ends_with(':') && !text.starts_with(':') => {
+                    self.define_label(&text[..text.len() - 1], line)?;
+                    i += 1;
+                }
+                _ if parse_number(text).is_some() => {
+                    self.byte(text, line)?;
+                    i += 1;
+                }
+                _ => i += self.instruction(&tokens[i..])?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Assembles the instruction at the start of `tokens`, returning how
+    /// many tokens it took up.
+    fn instruction(&mut self, tokens: &[Token]) -> Result<usize, AsmError> {
+        let line = tokens[0].line;
+
+        // the longest form that matches wins, so `i := long x` isn't read
+        // as `i := long` with a label called `long`
+        let mut best: Option<(usize, &OpcodeInfo, Operands)> = None;
+        for info in OPCODES {
+            for template in [info.classic, info.octo] {
+                let length = template_tokens(template).count();
+                if best.as_ref().is_some_and(|(best, _, _)| *best >= length) {
+                    continue;
+                }
+                if let Some(operands) = self.match_template(template, tokens) {
+                    best = Some((length, info, operands));
+                }
+            }
+        }
+
+        let (length, info, operands) = match best {
+            Some(best) => best,
+            None if is_name(&tokens[0].text) => {
+                // a bare label name is a call
+                let operands = Operands {
+                    nnn: Some(Address::Label(tokens[0].text.clone())),
+                    ..Operands::default()
+                };
+                (1, opcodes::lookup(0x2000).unwrap(), operands)
+            }
+            None => return error(line, format!("unknown instruction '{}'", tokens[0].text)),
+        };
+
+        let (_, value) = info.mask_value();
+        let mut opcode = value | operands.x << 8 | operands.y << 4 | operands.n | operands.kk;
+        let mut long = None;
+        let offset = self.rom.len();
+        if let Some(address) = operands.nnn {
+            opcode |= self.address(address, offset, FixupKind::Nnn, line)? as u16;
+        }
+        if let Some(address) = operands.long {
+            long = Some(self.address(address, offset + 2, FixupKind::Long, line)? as u16);
+        }
+        if let Some(address) = operands.far {
+            let far = self.address(address, offset, FixupKind::Far, line)?;
+            opcode |= (far >> 16) as u16;
+            long = Some(far as u16);
+        }
+        self.rom.extend_from_slice(&opcode.to_be_bytes());
+        if let Some(long) = long {
+            self.rom.extend_from_slice(&long.to_be_bytes());
+        }
+
+        Ok(length)
+    }
+
+    /// Matches source tokens against a mnemonic template from the opcode
+    /// table, collecting the operand fields it fills in.
+    fn match_template(&self, template: &str, tokens: &[Token]) -> Option<Operands> {
+        let mut operands = Operands::default();
+
+        for (index, expected) in template_tokens(template).enumerate() {
+            // an instruction is never split across lines, which also keeps
+            // `save v1` followed by `v1 ...` from reading as `SAVE V1, V1`
+            let token = tokens
+                .get(index)
+                .filter(|token| token.row == tokens[0].row)?;
+            let text = token.text.as_str();
+            let register = parse_register(text);
+            let small = self.value(text).filter(|value| (0..16).contains(value));
+
+            match expected.to_ascii_lowercase().as_str() {
+                "v{x}" => operands.x = register?,
+                "v{y}" => operands.y = register?,
+                "{x}" => operands.x = small? as u16,
+                "{n}" => operands.n = small? as u16,
+                "{kk}" => {
+                    let value = self
+                        .value(text)
+                        .filter(|value| (-128..256).contains(value))?;
+                    operands.kk = value as u16 & 0xFF;
+                }
+                "{nnn}" => operands.nnn = Some(self.address_operand(text)?),
+                "{long}" => operands.long = Some(self.address_operand(text)?),
+                "{far}" => operands.far = Some(self.address_operand(text)?),
+                literal => {
+                    if !text.eq_ignore_ascii_case(literal) {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        Some(operands)
+    }
+
+    fn address_operand(&self, text: &str) -> Option<Address> {
+        match self.value(text) {
+            Some(value) => Some(Address::Known(value as u32)),
+            None if is_name(text) => Some(Address::Label(text.to_string())),
+            None => None,
+        }
+    }
+
+    /// Value of an address operand, leaving a fixup for labels not yet
+    /// defined.
+    fn address(
+        &mut self,
+        address: Address,
+        offset: usize,
+        kind: FixupKind,
+        line: usize,
+    ) -> Result<u32, AsmError> {
+        let label = match address {
+            Address::Known(value) => return check_address(value, kind, line),
+            Address::Label(label) => label,
+        };
+        match self.labels.get(&label) {
+            Some(&value) => check_address(value as u32, kind, line),
+            None => {
+                self.fixups.push(Fixup {
+                    offset,
+                    kind,
+                    label,
+                    line,
+                });
+                Ok(0)
+            }
+        }
+    }
+
+    /// Fills in addresses of labels that were used before being defined.
+    fn resolve(&mut self) -> Result<(), AsmError> {
+        for fixup in &self.fixups {
+            let value = match self.labels.get(&fixup.label) {
+                Some(&value) => check_address(value as u32, fixup.kind, fixup.line)? as u16,
+                None => return error(fixup.line, format!("undefined label '{}'", fixup.label)),
+            };
+            // labels are 16 bits, so a far address only fills the word after
+            // the opcode
+            let offset = match fixup.kind {
+                FixupKind::Far => fixup.offset + 2,
+                _ => fixup.offset,
+            };
+            let word = &mut self.rom[offset..offset + 2];
+            let patched = match fixup.kind {
+                FixupKind::Nnn => u16::from_be_bytes([word[0], word[1]]) | value,
+                FixupKind::Long | FixupKind::Far => value,
+            };
+            word.copy_from_slice(&patched.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: &str, line: usize) -> Result<(), AsmError> {
+        self.check_name(name, line)?;
+        let here = self.here();
+        self.labels.insert(name.to_string(), here);
+        Ok(())
+    }
+
+    fn define_macro(
+        &mut self,
+        tokens: &[Token],
+        start: usize,
+        line: usize,
+    ) -> Result<usize, AsmError> {
+        let name = operand(tokens, start + 1, line)?;
+        self.check_name(name, line)?;
+
+        let mut i = start + 2;
+        let mut params = Vec::new();
+        while i < tokens.len() && tokens[i].text != "{" {
+            params.push(tokens[i].text.clone());
+            i += 1;
+        }
+
+        let body_start = i + 1;
+        let mut depth = 0;
+        loop {
+            match tokens.get(i).map(|token| token.text.as_str()) {
+                Some("{") => depth += 1,
+                Some("}") => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                Some(_) => {}
+                None => return error(line, format!("macro '{}' has no closing '}}'", name)),
+            }
+            i += 1;
+        }
+
+        let body = tokens[body_start..i].to_vec();
+        self.macros.insert(name.to_string(), Macro { params, body });
+        Ok(i + 1)
+    }
+
+    fn check_name(&self, name: &str, line: usize) -> Result<(), AsmError> {
+        if !is_name(name) {
+            return error(line, format!("bad name '{}'", name));
+        }
+        if self.labels.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.macros.contains_key(name)
+        {
+            return error(line, format!("'{}' is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, text: &str, line: usize) -> Result<(), AsmError> {
+        match self.value(text) {
+            Some(value) if (-128..256).contains(&value) => {
+                self.rom.push(value as u8);
+                Ok(())
+            }
+            _ => error(line, format!("bad byte '{}'", text)),
+        }
+    }
+
+    /// A number, or the value of a constant or already defined label.
+    fn value(&self, text: &str) -> Option<i32> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).map(|&value| value as i32))
+            .or_else(|| self.labels.get(text).map(|&value| value as i32))
+    }
+}
+
+fn operand(tokens: &[Token], index: usize, line: usize) -> Result<&str, AsmError> {
+    match tokens.get(index) {
+        Some(token) => Ok(&token.text),
+        None => error(line, String::from("unexpected end of source")),
+    }
+}
+
+fn template_tokens(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+}
+
+fn check_address(value: u32, kind: FixupKind, line: usize) -> Result<u32, AsmError> {
+    match kind {
+        FixupKind::Nnn if value > 0xFFF => error(
+            line,
+            format!("address 0x{:X} is out of range, use 'i := long'", value),
+        ),
+        FixupKind::Long if value > 0xFFFF => {
+            error(line, format!("address 0x{:X} is out of range", value))
+        }
+        FixupKind::Far if value > 0xFFFFFF => {
+            error(line, format!("address 0x{:X} is out of range", value))
+        }
+        _ => Ok(value),
+    }
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Register number of `v0`..`vf`, in either case.
+fn parse_register(text: &str) -> Option<u16> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    digit.chars().next()?.to_digit(16).map(|digit| digit as u16)
+}
+
+/// Whether `text` can name a label, constant or macro.
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && parse_register(text).is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_at(source: &str) -> (usize, String) {
+        let err = assemble(source, 0x200).unwrap_err();
+        (err.line, err.message)
+    }
+
+    #[test]
+    fn both_syntaxes_assemble_alike() {
+        let octo = ": main\n v1 := 0x20\n sprite v0 v1 5\n i := long main\n jump main";
+        let classic = "main:\n LD V1, 0x20\n DRW V0, V1, 5\n LD I, LONG main\n JP main";
+        let rom = vec![0x61, 0x20, 0xD0, 0x15, 0xF0, 0x00, 0x02, 0x00, 0x12, 0x00];
+        assert_eq!(assemble(octo, 0x200), Ok(rom.clone()));
+        assert_eq!(assemble(classic, 0x200), Ok(rom));
+    }
+
+    #[test]
+    fn labels_resolve_forwards_and_backwards() {
+        let source = ": main\n jump later\n: later\n i := ldhi later\n jump main";
+        let rom = vec![0x12, 0x02, 0x01, 0x00, 0x02, 0x02, 0x12, 0x00];
+        assert_eq!(assemble(source, 0x200), Ok(rom));
+    }
+
+    #[test]
+    fn unknown_instructions_are_errors() {
+        assert_eq!(
+            error_at("v0 := 1\nv0 <=> v1"),
+            (2, String::from("unknown instruction 'v0'"))
+        );
+        assert_eq!(
+            error_at("%%"),
+            (1, String::from("unknown instruction '%%'"))
+        );
+    }
+
+    #[test]
+    fn out_of_range_operands_are_errors() {
+        // a byte too big for `v0 := kk` matches no instruction
+        assert_eq!(
+            error_at("v0 := 256"),
+            (1, String::from("unknown instruction 'v0'"))
+        );
+        assert_eq!(
+            error_at("\njump 0x1000"),
+            (
+                2,
+                String::from("address 0x1000 is out of range, use 'i := long'")
+            )
+        );
+        assert_eq!(
+            error_at("i := long 0x10000"),
+            (1, String::from("address 0x10000 is out of range"))
+        );
+        assert_eq!(
+            error_at("i := ldhi 0x1000000"),
+            (1, String::from("address 0x1000000 is out of range"))
+        );
+        assert_eq!(error_at(":byte 300"), (1, String::from("bad byte '300'")));
+    }
+
+    #[test]
+    fn undefined_labels_are_errors() {
+        assert_eq!(
+            error_at(": main\n jump main\n i := nowhere"),
+            (3, String::from("undefined label 'nowhere'"))
+        );
+        // a bare name is a call
+        assert_eq!(
+            error_at("frobnicate"),
+            (1, String::from("undefined label 'frobnicate'"))
+        );
+    }
+
+    #[test]
+    fn names_are_defined_once() {
+        assert_eq!(
+            error_at(": main\n: main"),
+            (2, String::from("'main' is already defined"))
+        );
+    }
+
+    #[test]
+    fn macro_bodies_keep_their_lines() {
+        let plain = "save v1\nv1 := 3";
+        let rom = vec![0xF1, 0x55, 0x61, 0x03];
+        assert_eq!(assemble(plain, 0x200), Ok(rom.clone()));
+        let source = ":macro m {\n save v1\n v1 := 3\n}\nm";
+        assert_eq!(assemble(source, 0x200), Ok(rom.clone()));
+        // nor does the end of one expansion run into the next line
+        let source = ":macro m reg { save reg }\nm v1\nv1 := 3";
+        assert_eq!(assemble(source, 0x200), Ok(rom));
+        assert_eq!(
+            error_at(":macro m {\n v0 := 1\n v0 <=> v1\n}\n\nm"),
+            (6, String::from("unknown instruction 'v0'"))
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::opcodes::{self, OpcodeInfo, Syntax};
+use crate::processor::Fields;
+
+// opcodes that conditionally skip the following instruction
+const SKIPS: [&str; 8] = [
+    "3XKK", "4XKK", "5XY0", "9XY0", "EX9E", "EXA1", "EXF2", "EXF5",
+];
+
+// data bytes per line
+const BYTES_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    // ordered so a call target's name wins over a jump's, and a jump's over
+    // a data reference's
+    Data,
+    Jump,
+    Call,
+}
+
+/// Mnemonic for a single opcode, or the opcode as data if it isn't an
+/// instruction. `long` is the word following the opcode, only used by the
+/// four-byte `F000` and `01KK`.
+pub fn instruction(opcode: u16, long: u16, syntax: Syntax) -> String {
+    match opcodes::lookup(opcode) {
+        Some(info) => render(info, opcode, long, syntax, &BTreeMap::new()),
+        None => data_word(opcode, syntax),
+    }
+}
+
+/// Disassembles `bytes`, loaded at `origin`, into source the assembler
+/// accepts back.
+///
+/// Code is found by following control flow from `origin`, so bytes only
+/// reachable as data (sprites, tables) are written as data rather than
+/// misread as instructions. Jump and call targets and `I` references get
+/// labels.
+pub fn disassemble(bytes: &[u8], origin: u16, syntax: Syntax) -> String {
+    let code = trace(bytes, origin);
+    let labels = name_labels(bytes, origin, &code, syntax);
+
+    let mut out = String::new();
+    let mut data: Vec<u8> = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let addr = origin.wrapping_add(offset as u16);
+        let label = labels.get(&addr);
+
+        if data.len() == BYTES_PER_LINE || (!data.is_empty() && (label.is_some() || code[offset])) {
+            write_data(&mut out, &data, syntax);
+            data.clear();
+        }
+        if let Some(label) = label {
+            match syntax {
+                Syntax::Classic => writeln!(out, "{}:", label).unwrap(),
+                Syntax::Octo => writeln!(out, ": {}", label).unwrap(),
+            }
+        }
+
+        if !code[offset] {
+            data.push(bytes[offset]);
+            offset += 1;
+            continue;
+        }
+
+        let opcode = read_word(bytes, offset);
+        let long = read_word(bytes, offset + 2);
+        let info = opcodes::lookup(opcode).unwrap();
+        let text = render(info, opcode, long, syntax, &labels);
+        let comment = match syntax {
+            Syntax::Classic => ';',
+            Syntax::Octo => '#',
+        };
+        writeln!(out, "    {:<28}{} 0x{:03X}", text, comment, addr).unwrap();
+        offset += info.size as usize;
+    }
+    write_data(&mut out, &data, syntax);
+
+    out
+}
+
+/// The address and opcode of every instruction reachable from the start of
+/// `bytes`, in address order.
+pub(crate) fn traced_opcodes(bytes: &[u8], origin: u16) -> Vec<(u16, u16)> {
+    let code = trace(bytes, origin);
+    let mut opcodes = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        if !code[offset] {
+            offset += 1;
+            continue;
+        }
+        let opcode = read_word(bytes, offset);
+        opcodes.push((origin.wrapping_add(offset as u16), opcode));
+        offset += opcodes::lookup(opcode).unwrap().size as usize;
+    }
+
+    opcodes
+}
+
+/// Marks every byte that belongs to an instruction reachable from the start
+/// of `bytes`.
+fn trace(bytes: &[u8], origin: u16) -> Vec<bool> {
+    let mut code = vec![false; bytes.len()];
+    let mut pending = vec![origin];
+
+    while let Some(addr) = pending.pop() {
+        let offset = addr.wrapping_sub(origin) as usize;
+        if offset + 1 >= bytes.len() || code[offset] {
+            continue;
+        }
+
+        let opcode = read_word(bytes, offset);
+        let info = match opcodes::lookup(opcode) {
+            Some(info) => info,
+            None => continue,
+        };
+        let len = info.size as usize;
+        if offset + len > bytes.len() || code[offset..offset + len].contains(&true) {
+            continue;
+        }
+        code[offset..offset + len].fill(true);
+
+        let next = addr.wrapping_add(len as u16);
+        let nnn = Fields::decode(opcode).nnn as u16;
+        match info.pattern {
+            "00EE" | "00FD" => {}
+            "1NNN" => pending.push(nnn),
+            "2NNN" => pending.extend([nnn, next]),
+            // the jump target depends on V0, but the table usually starts
+            // at the base address
+            "BNNN" => pending.push(nnn),
+            pattern if SKIPS.contains(&pattern) => {
+                let following = read_word(bytes, next.wrapping_sub(origin) as usize);
+                let skipped = opcodes::lookup(following).map_or(2, |info| info.size);
+                pending.extend([next, next.wrapping_add(skipped)]);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    code
+}
+
+/// Names the addresses referenced by the traced code, keeping only those a
+/// label can be placed at: the start of an instruction or a data byte.
+fn name_labels(bytes: &[u8], origin: u16, code: &[bool], syntax: Syntax) -> BTreeMap<u16, String> {
+    let mut kinds: BTreeMap<u16, Label> = BTreeMap::new();
+    let mut starts = vec![false; bytes.len()];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        if !code[offset] {
+            offset += 1;
+            continue;
+        }
+        starts[offset] = true;
+
+        let opcode = read_word(bytes, offset);
+        let info = opcodes::lookup(opcode).unwrap();
+        let nnn = Fields::decode(opcode).nnn as u16;
+        let reference = match info.pattern {
+            "1NNN" | "BNNN" => Some((nnn, Label::Jump)),
+            "2NNN" => Some((nnn, Label::Call)),
+            "ANNN" => Some((nnn, Label::Data)),
+            "F000" => Some((read_word(bytes, offset + 2), Label::Data)),
+            "01KK" if opcode & 0xFF == 0 => Some((read_word(bytes, offset + 2), Label::Data)),
+            _ => None,
+        };
+        if let Some((target, kind)) = reference {
+            let entry = kinds.entry(target).or_insert(kind);
+            *entry = (*entry).max(kind);
+        }
+        offset += info.size as usize;
+    }
+
+    let mut labels: BTreeMap<u16, String> = kinds
+        .into_iter()
+        .filter(|&(addr, _)| {
+            let offset = addr.wrapping_sub(origin) as usize;
+            offset < bytes.len() && (starts[offset] || !code[offset])
+        })
+        .map(|(addr, kind)| {
+            let prefix = match kind {
+                Label::Data => "data",
+                Label::Jump => "label",
+                Label::Call => "sub",
+            };
+            (addr, format!("{}_{:03X}", prefix, addr))
+        })
+        .collect();
+
+    if syntax == Syntax::Octo && !bytes.is_empty() {
+        // Octo programs start at `main`
+        labels.insert(origin, String::from("main"));
+    }
+
+    labels
+}
+
+/// Fills in a mnemonic template with the opcode's fields, naming addresses
+/// with their labels where there is one.
+fn render(
+    info: &OpcodeInfo,
+    opcode: u16,
+    long: u16,
+    syntax: Syntax,
+    labels: &BTreeMap<u16, String>,
+) -> String {
+    let fields = Fields::decode(opcode);
+    let address = |addr: u16, digits: usize| match labels.get(&addr) {
+        Some(label) => label.clone(),
+        None => format!("0x{:01$X}", addr, digits),
+    };
+
+    let template = info.mnemonic(syntax);
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}').unwrap();
+        out.push_str(&rest[..start]);
+
+        // registers are a hex digit after `V`, other nibbles are numbers
+        let register = out.ends_with(['V', 'v']);
+        let value = match &rest[start + 1..end] {
+            "x" if register && syntax == Syntax::Octo => format!("{:x}", fields.x),
+            "x" if register => format!("{:X}", fields.x),
+            "x" => fields.x.to_string(),
+            "y" if syntax == Syntax::Octo => format!("{:x}", fields.y),
+            "y" => format!("{:X}", fields.y),
+            "n" => fields.n.to_string(),
+            "kk" => format!("0x{:02X}", fields.kk),
+            "nnn" => address(fields.nnn as u16, 3),
+            "long" => address(long, 4),
+            "far" if fields.kk == 0 => address(long, 6),
+            "far" => format!("0x{:02X}{:04X}", fields.kk, long),
+            field => unreachable!("unknown field {{{}}}", field),
+        };
+        out.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn data_word(word: u16, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Classic => format!("DW 0x{:04X}", word),
+        Syntax::Octo => format!("0x{:02X} 0x{:02X}", word >> 8, word & 0xFF),
+    }
+}
+
+fn write_data(out: &mut String, data: &[u8], syntax: Syntax) {
+    if data.is_empty() {
+        return;
+    }
+
+    let bytes: Vec<String> = data.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+    match syntax {
+        Syntax::Classic => writeln!(out, "    DB {}", bytes.join(", ")).unwrap(),
+        Syntax::Octo => writeln!(out, "    {}", bytes.join(" ")).unwrap(),
+    }
+}
+
+/// Big-endian word at `offset`, reading past the end as zero.
+fn read_word(bytes: &[u8], offset: usize) -> u16 {
+    let high = bytes.get(offset).copied().unwrap_or(0) as u16;
+    let low = bytes.get(offset + 1).copied().unwrap_or(0) as u16;
+    (high << 8) | low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::opcodes::OPCODES;
+
+    const PROGRAM: &str = "
+        : main
+            v0 := 0
+            i := sprite
+            if v0 != 3 then
+            i := long sprite
+            if v1 -key then
+            i := ldhi 0x012345
+            draw
+            :call draw
+            v1 := random 0x0F
+            jump0 table
+        : table
+            jump main
+        : draw
+            sprite v0 v0 4
+            return
+        : sprite
+            0xF0 0x90 0x90 0xF0
+    ";
+
+    /// Every instruction in the table disassembles to a mnemonic that
+    /// assembles back to the same bytes.
+    #[test]
+    fn every_instruction_round_trips() {
+        for syntax in [Syntax::Classic, Syntax::Octo] {
+            for info in OPCODES {
+                // sample operands in the fields the pattern leaves open
+                let (mask, value) = info.mask_value();
+                let opcode = value | (0x5A3C & !mask);
+                let mut bytes = opcode.to_be_bytes().to_vec();
+                if info.size == 4 {
+                    bytes.extend_from_slice(&[0x12, 0x34]);
+                }
+
+                let text = instruction(opcode, 0x1234, syntax);
+                assert_eq!(assemble(&text, 0x200), Ok(bytes), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn programs_round_trip_in_both_syntaxes() {
+        let rom = assemble(PROGRAM, 0x200).unwrap();
+        for syntax in [Syntax::Classic, Syntax::Octo] {
+            let source = disassemble(&rom, 0x200, syntax);
+            assert_eq!(assemble(&source, 0x200), Ok(rom.clone()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn traced_code_and_data_are_told_apart() {
+        let rom = assemble(PROGRAM, 0x200).unwrap();
+        let source = disassemble(&rom, 0x200, Syntax::Octo);
+        assert!(source.contains("i := ldhi 0x012345"));
+        assert!(source.contains(": sub_"));
+        assert!(source.ends_with("    0xF0 0x90 0x90 0xF0\n"));
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt::Write;
+
+use crate::processor::{InstructionSet, Processor};
+
+// grey levels for each combination of lit drawing planes, as in the window
+const PALETTE: [u8; 4] = [0, 255, 170, 85];
+
+/// The visible screen as text, one line per row: `.` for an unlit pixel,
+/// `#` for one lit on the first plane, and the plane mask as a digit
+/// otherwise. In MegaChip mode any pixel that isn't black is `#`.
+pub fn screen_text<T: InstructionSet>(chippy: &Processor<T>) -> String {
+    let (width, height) = chippy.get_resolution();
+    let mut out = String::with_capacity((width + 1) * height);
+
+    if let Some(frame) = chippy.get_framebuffer() {
+        for row in frame.chunks(width) {
+            out.extend(row.iter().map(|&argb| match argb & 0xFF_FFFF {
+                0 => '.',
+                _ => '#',
+            }));
+            out.push('\n');
+        }
+        return out;
+    }
+
+    for row in chippy.get_screen().iter().take(height) {
+        for &pixel in row.iter().take(width) {
+            out.push(match pixel {
+                0 => '.',
+                1 => '#',
+                planes => char::from_digit(planes as u32, 16).unwrap_or('?'),
+            });
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// The visible screen as a greyscale PNG, one image pixel per CHIP-8
+/// pixel. MegaChip colours are reduced to their luminance.
+pub fn screen_png<T: InstructionSet>(chippy: &Processor<T>) -> Vec<u8> {
+    let (width, height) = chippy.get_resolution();
+
+    // each scanline is a filter type byte (none) followed by the pixels
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    if let Some(frame) = chippy.get_framebuffer() {
+        for row in frame.chunks(width) {
+            raw.push(0);
+            raw.extend(row.iter().map(|&argb| luminance(argb)));
+        }
+    } else {
+        for row in chippy.get_screen().iter().take(height) {
+            raw.push(0);
+            raw.extend(
+                row.iter()
+                    .take(width)
+                    .map(|&pixel| PALETTE[pixel as usize & 0x03]),
+            );
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]); // 8-bit greyscale, no interlace
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Rec. 601 luma of an ARGB colour.
+fn luminance(argb: u32) -> u8 {
+    let [_, r, g, b] = argb.to_be_bytes();
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+/// Registers, timers and stack as a JSON object.
+pub fn registers_json<T: InstructionSet>(chippy: &Processor<T>) -> String {
+    let (delay_timer, sound_timer) = chippy.get_timers();
+    let join = |values: Vec<String>| values.join(", ");
+
+    let mut out = String::from("{\n");
+    writeln!(out, "  \"pc\": {},", chippy.get_pc()).unwrap();
+    writeln!(out, "  \"i\": {},", chippy.get_index()).unwrap();
+    writeln!(
+        out,
+        "  \"v\": [{}],",
+        join(chippy.get_registers().iter().map(u8::to_string).collect())
+    )
+    .unwrap();
+    writeln!(out, "  \"sp\": {},", chippy.get_sp()).unwrap();
+    writeln!(
+        out,
+        "  \"stack\": [{}],",
+        join(chippy.get_stack().iter().map(u16::to_string).collect())
+    )
+    .unwrap();
+    writeln!(out, "  \"delay_timer\": {},", delay_timer).unwrap();
+    writeln!(out, "  \"sound_timer\": {},", sound_timer).unwrap();
+    writeln!(out, "  \"halted\": {}", chippy.halted()).unwrap();
+    out.push_str("}\n");
+    out
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of uncompressed deflate blocks; screens are
+/// small enough that compressing them isn't worth the code.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::CosmacVip;
+    use crate::quirks::Quirks;
+
+    /// A VIP showing the font's `0` in the top left corner.
+    fn zero() -> Processor<CosmacVip> {
+        let mut chippy = Processor::new(CosmacVip, Quirks::VIP);
+        // V0 := 0, I := hex 0, draw 5 rows at V0, V0
+        chippy
+            .load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05])
+            .unwrap();
+        chippy.run_frame().unwrap();
+        chippy
+    }
+
+    /// Splits a PNG into its chunks, checking each one's CRC.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            assert_eq!(crc32(&rest[4..8 + len]), crc);
+            chunks.push((rest[4..8].try_into().unwrap(), &rest[8..8 + len]));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn screens_are_written_as_text() {
+        let text = screen_text(&zero());
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows.len(), 32);
+        assert_eq!(&rows[0][..6], "####..");
+        assert_eq!(&rows[1][..6], "#..#..");
+        assert!(rows[5].chars().all(|pixel| pixel == '.'));
+    }
+
+    #[test]
+    fn screens_are_written_as_png() {
+        let png = screen_png(&zero());
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 64, 0, 0, 0, 32, 8, 0, 0, 0, 0]);
+
+        // one stored block holding every scanline, filter byte first
+        let idat = chunks[1].1;
+        let raw_len = 65 * 32;
+        assert_eq!(&idat[..3], [0x78, 0x01, 1]);
+        assert_eq!(u16::from_le_bytes([idat[3], idat[4]]) as usize, raw_len);
+        let raw = &idat[7..7 + raw_len];
+        assert_eq!(raw[..7], [0, 255, 255, 255, 255, 0, 0]);
+        assert_eq!(raw[65..72], [0, 255, 0, 0, 255, 0, 0]);
+        assert_eq!(&idat[7 + raw_len..], adler32(raw).to_be_bytes());
+    }
+
+    #[test]
+    fn registers_are_written_as_json() {
+        let json = registers_json(&zero());
+        assert!(json.starts_with("{\n  \"pc\": 518,\n"));
+        assert!(json.contains("\"sp\": 0,"));
+        assert!(json.ends_with("\"halted\": false\n}\n"));
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can stop the emulator, from a bad ROM file to a program
+/// that misbehaves while running.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// the ROM is bigger than the memory it is loaded into
+    RomTooLarge {
+        size: usize,
+        capacity: usize,
+    },
+    /// a call at `pc` with all sixteen stack entries in use
+    StackOverflow {
+        pc: u16,
+    },
+    /// a return at `pc` with nothing on the stack
+    StackUnderflow {
+        pc: u16,
+    },
+    /// execution ran off the end of memory
+    PcOutOfRange {
+        pc: u16,
+    },
+    /// an opcode the instruction set doesn't define
+    InvalidOpcode {
+        pc: u16,
+        opcode: u16,
+    },
+    /// a save state that can't be restored, and why
+    SaveState(&'static str),
+    /// an input recording that can't be played back, and why
+    Movie(&'static str),
+    /// a random number generator name `random::from_name` doesn't know
+    UnknownRng(String),
+    /// a headless keypad script that doesn't parse
+    KeyScript {
+        line: usize,
+        message: String,
+    },
+    /// a keymap file that doesn't parse
+    Keymap {
+        line: usize,
+        message: String,
+    },
+    /// a ROM database file that doesn't parse
+    RomDatabase {
+        line: usize,
+        message: String,
+    },
+    /// SDL failed to start the window, input or audio
+    Sdl(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::RomTooLarge { size, capacity } => write!(
+                f,
+                "ROM is {} bytes but only {} fit in memory",
+                size, capacity
+            ),
+            Error::StackOverflow { pc } => write!(f, "stack overflow at 0x{:03X}", pc),
+            Error::StackUnderflow { pc } => write!(f, "stack underflow at 0x{:03X}", pc),
+            Error::PcOutOfRange { pc } => write!(f, "PC 0x{:03X} is outside memory", pc),
+            Error::InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode {:04X} at 0x{:03X}", opcode, pc)
+            }
+            Error::SaveState(message) | Error::Movie(message) => write!(f, "{}", message),
+            Error::UnknownRng(name) => write!(f, "unknown random number generator '{}'", name),
+            Error::KeyScript { line, message } => {
+                write!(f, "key script line {}: {}", line, message)
+            }
+            Error::Keymap { line, message } => write!(f, "keymap line {}: {}", line, message),
+            Error::RomDatabase { line, message } => {
+                write!(f, "ROM database line {}: {}", line, message)
+            }
+            Error::Sdl(message) => write!(f, "SDL error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,278 @@
+use crate::error::Error;
+use crate::movie::Movie;
+use crate::processor::{InstructionSet, Processor};
+
+/// Where a headless run gets its keypad from.
+pub trait KeySource {
+    /// Updates the keypad for the start of `frame`.
+    fn apply(&self, frame: usize, keypad: &mut [bool; 16]);
+
+    /// Updates CHIP-8X's second keypad for the start of `frame`.
+    fn apply_second(&self, _frame: usize, _keypad: &mut [bool; 16]) {}
+}
+
+/// Keypad input for a headless run: which keys go down and come up on
+/// which frame.
+///
+/// The script format is one frame number per line followed by the changes
+/// made at the start of that frame, `+k` to press hex key `k` and `-k` to
+/// release it. Keys stay as they are until changed. `#` starts a comment.
+///
+/// ```text
+/// # hold 5 for two frames, then press A
+/// 60 +5
+/// 62 -5 +a
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    // (frame, key, pressed), in frame order
+    events: Vec<(usize, usize, bool)>,
+}
+
+impl KeyScript {
+    pub fn parse(script: &str) -> Result<Self, Error> {
+        let mut events = Vec::new();
+
+        for (index, line) in script.lines().enumerate() {
+            let error = |message: &str| Error::KeyScript {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let code = line.split('#').next().unwrap_or_default();
+            let mut words = code.split_whitespace();
+            let frame = match words.next() {
+                Some(frame) => frame
+                    .parse()
+                    .map_err(|_| error("expected a frame number"))?,
+                None => continue,
+            };
+
+            for word in words {
+                let pressed = match word.chars().next() {
+                    Some('+') => true,
+                    Some('-') => false,
+                    _ => return Err(error("expected +key or -key")),
+                };
+                let key = match u8::from_str_radix(&word[1..], 16) {
+                    Ok(key) if key < 16 => key as usize,
+                    _ => return Err(error("keys are hex digits 0 to f")),
+                };
+                events.push((frame, key, pressed));
+            }
+        }
+
+        // keep the order of changes made on the same frame
+        events.sort_by_key(|&(frame, _, _)| frame);
+        Ok(KeyScript { events })
+    }
+}
+
+impl KeySource for KeyScript {
+    fn apply(&self, frame: usize, keypad: &mut [bool; 16]) {
+        for &(_, key, pressed) in self.events.iter().filter(|event| event.0 == frame) {
+            keypad[key] = pressed;
+        }
+    }
+}
+
+/// Plays the recorded keypads back; once the recording runs out the keys
+/// stay as they were on its last frame.
+impl KeySource for Movie {
+    fn apply(&self, frame: usize, keypad: &mut [bool; 16]) {
+        if let Some(recorded) = self.keypad(frame) {
+            *keypad = recorded;
+        }
+    }
+
+    fn apply_second(&self, frame: usize, keypad: &mut [bool; 16]) {
+        if let Some(recorded) = self.keypad2(frame) {
+            *keypad = recorded;
+        }
+    }
+}
+
+/// When a headless run stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunLimits {
+    /// frames to run before giving up
+    pub frames: usize,
+    /// stop before executing the instruction at this address
+    pub until_pc: Option<u16>,
+    /// stop at a `1NNN` that jumps to itself, the usual way a test ROM
+    /// signals it has finished
+    pub until_spin: bool,
+}
+
+/// Why a headless run stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Frames,
+    Pc(u16),
+    Spin(u16),
+    Halted,
+}
+
+/// Outcome of a headless run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunResult {
+    pub stop: Stop,
+    /// frames started, including the one the run stopped in
+    pub frames: usize,
+}
+
+/// Runs a loaded program without a display at full speed, feeding it
+/// scripted or recorded keys, until one of the limits is reached.
+/// `on_frame` sees the machine after each complete frame, for rendering
+/// its sound and reporting skipped opcodes.
+pub fn run<T: InstructionSet>(
+    chippy: &mut Processor<T>,
+    limits: &RunLimits,
+    keys: &dyn KeySource,
+    on_frame: &mut dyn FnMut(&mut Processor<T>),
+) -> Result<RunResult, Error> {
+    let mut keypad = [false; 16];
+    let mut keypad2 = [false; 16];
+
+    for frame in 0..limits.frames {
+        keys.apply(frame, &mut keypad);
+        keys.apply_second(frame, &mut keypad2);
+        chippy.set_keypad(&keypad);
+        chippy.set_keypad2(&keypad2);
+
+        for _ in 0..chippy.get_ipf() {
+            if !chippy.ready() {
+                break;
+            }
+
+            let pc = chippy.get_pc();
+            let spinning = pc <= 0x0FFF && chippy.get_opcode_at(pc) == 0x1000 | pc;
+            let stop = if limits.until_pc == Some(pc) {
+                Some(Stop::Pc(pc))
+            } else if limits.until_spin && spinning {
+                Some(Stop::Spin(pc))
+            } else {
+                None
+            };
+            if let Some(stop) = stop {
+                return Ok(RunResult {
+                    stop,
+                    frames: frame + 1,
+                });
+            }
+
+            chippy.tick()?;
+        }
+        chippy.end_frame();
+        on_frame(chippy);
+
+        if chippy.halted() {
+            return Ok(RunResult {
+                stop: Stop::Halted,
+                frames: frame + 1,
+            });
+        }
+    }
+
+    Ok(RunResult {
+        stop: Stop::Frames,
+        frames: limits.frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::{CosmacVip, SuperChip};
+    use crate::quirks::Quirks;
+
+    // V0 := 1, then a jump to itself
+    const SPIN: [u8; 4] = [0x60, 0x01, 0x12, 0x02];
+
+    fn run_rom<T: InstructionSet>(chippy: &mut Processor<T>, limits: RunLimits) -> RunResult {
+        run(chippy, &limits, &KeyScript::default(), &mut |_| {}).unwrap()
+    }
+
+    fn limits(frames: usize) -> RunLimits {
+        RunLimits {
+            frames,
+            until_pc: None,
+            until_spin: false,
+        }
+    }
+
+    #[test]
+    fn scripts_press_and_release_keys() {
+        let script = KeyScript::parse("# comment\n2 +5 +A\n\n4 -5 # let go\n").unwrap();
+        let mut keypad = [false; 16];
+        script.apply(1, &mut keypad);
+        assert_eq!(keypad, [false; 16]);
+        script.apply(2, &mut keypad);
+        assert!(keypad[5] && keypad[0xA]);
+        script.apply(4, &mut keypad);
+        assert!(!keypad[5] && keypad[0xA]);
+    }
+
+    #[test]
+    fn script_errors_give_the_line() {
+        for (script, line, message) in [
+            ("x +1", 1, "expected a frame number"),
+            ("1 +1\n2 5", 2, "expected +key or -key"),
+            ("\n\n3 +g", 3, "keys are hex digits 0 to f"),
+            ("3 -10", 1, "keys are hex digits 0 to f"),
+        ] {
+            match KeyScript::parse(script) {
+                Err(Error::KeyScript {
+                    line: found,
+                    message: text,
+                }) => assert_eq!((found, text.as_str()), (line, message), "{}", script),
+                other => panic!("{}: {:?}", script, other),
+            }
+        }
+    }
+
+    #[test]
+    fn runs_stop_at_a_jump_to_itself() {
+        let mut chippy = Processor::new(CosmacVip, Quirks::VIP);
+        chippy.load_rom(&SPIN).unwrap();
+        let result = run_rom(
+            &mut chippy,
+            RunLimits {
+                until_spin: true,
+                ..limits(10)
+            },
+        );
+        assert_eq!(result.stop, Stop::Spin(0x202));
+        assert_eq!(result.frames, 1);
+        assert_eq!(chippy.get_registers()[0], 1);
+    }
+
+    #[test]
+    fn runs_stop_at_an_address_or_after_the_frames() {
+        let mut chippy = Processor::new(CosmacVip, Quirks::VIP);
+        chippy.load_rom(&SPIN).unwrap();
+        let result = run_rom(
+            &mut chippy,
+            RunLimits {
+                until_pc: Some(0x202),
+                ..limits(10)
+            },
+        );
+        assert_eq!(result.stop, Stop::Pc(0x202));
+
+        let result = run_rom(&mut chippy, limits(3));
+        assert_eq!(
+            result,
+            RunResult {
+                stop: Stop::Frames,
+                frames: 3
+            }
+        );
+    }
+
+    #[test]
+    fn runs_stop_when_the_program_exits() {
+        let mut chippy = Processor::new(SuperChip, Quirks::SCHIP_1_1);
+        chippy.load_rom(&[0x00, 0xFD]).unwrap();
+        assert_eq!(run_rom(&mut chippy, limits(10)).stop, Stop::Halted);
+    }
+}
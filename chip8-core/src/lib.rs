@@ -0,0 +1,25 @@
+//! CHIP-8, HIRES CHIP-8, CHIP-8X, ETI-660, SUPER-CHIP, MegaChip and XO-CHIP
+//! interpretation with no dependency on a display, input or audio backend,
+//! plus the assembler and disassembler built on the same opcode table.
+
+pub mod asm;
+pub mod disasm;
+pub mod dump;
+pub mod error;
+pub mod headless;
+pub mod megachip;
+pub mod movie;
+pub mod opcodes;
+pub mod processor;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod romdb;
+pub mod savestate;
+
+pub use error::Error;
+pub use processor::{
+    Chip8X, CosmacVip, Eti660, HiresChip, InstructionSet, MegaChip, Processor, ProcessorState,
+    SuperChip, XoChip,
+};
+pub use quirks::Quirks;
@@ -0,0 +1,421 @@
+use crate::error::Error;
+use crate::savestate::{Decoder, Encoder};
+
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+const PIXELS: usize = MEGA_WIDTH * MEGA_HEIGHT;
+
+// bytes before the samples of a digitized sound: a 16-bit sample rate, a
+// 24-bit length and a reserved byte
+const SOUND_HEADER: usize = 6;
+const FRAME_RATE: u32 = 60;
+
+/// How sprite pixels combine with what is already on the screen, as set by
+/// 080N.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    Normal,
+    Quarter,
+    Half,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    fn from_code(code: u8) -> Option<Blend> {
+        match code {
+            0 => Some(Blend::Normal),
+            1 => Some(Blend::Quarter),
+            2 => Some(Blend::Half),
+            3 => Some(Blend::Add),
+            4 => Some(Blend::Multiply),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// `src` drawn over `dst`, both ARGB, at the source's own alpha scaled
+    /// by this mode.
+    fn apply(self, src: u32, dst: u32) -> u32 {
+        let alpha = src >> 24;
+        let alpha = match self {
+            Blend::Quarter => alpha / 4,
+            Blend::Half => alpha / 2,
+            _ => alpha,
+        };
+
+        let channel = |shift: u32| {
+            let s = (src >> shift) & 0xFF;
+            let d = (dst >> shift) & 0xFF;
+            let mixed = match self {
+                Blend::Add => (d + s * alpha / 255).min(255),
+                Blend::Multiply => d - (d - d * s / 255) * alpha / 255,
+                _ => d + s * alpha / 255 - d * alpha / 255,
+            };
+            mixed << shift
+        };
+        0xFF00_0000 | channel(16) | channel(8) | channel(0)
+    }
+}
+
+/// A digitized sound started by 060N, playing from memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sound {
+    start: usize,
+    len: usize,
+    rate: u32,
+    looping: bool,
+    // samples played, times the frame rate so fractions aren't lost
+    ticks: u64,
+}
+
+/// MegaChip's 256x192 colour display and digitized sound.
+///
+/// Sprites are drawn into a back buffer that the program shows with
+/// 00E0, which then clears it for the next frame. Every pixel is an ARGB
+/// colour from the 255-entry palette; the palette index of the last
+/// sprite pixel drawn on it is kept alongside for collision checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MegaScreen {
+    /// whether MegaChip mode is on, rather than SUPER-CHIP's display
+    pub enabled: bool,
+    palette: [u32; 256],
+    sprite_width: usize,
+    sprite_height: usize,
+    // fade applied to the whole screen when it is shown
+    alpha: u8,
+    blend: Blend,
+    collision_color: u8,
+    indices: Vec<u8>,
+    back: Vec<u32>,
+    front: Vec<u32>,
+    sound: Option<Sound>,
+    // samples played during the last frame, and their rate
+    played: Vec<u8>,
+    played_rate: u32,
+}
+
+impl Default for MegaScreen {
+    fn default() -> Self {
+        MegaScreen {
+            enabled: false,
+            palette: [0; 256],
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xFF,
+            blend: Blend::Normal,
+            collision_color: 0,
+            indices: vec![0; PIXELS],
+            back: vec![0; PIXELS],
+            front: vec![0; PIXELS],
+            sound: None,
+            played: Vec::new(),
+            played_rate: 0,
+        }
+    }
+}
+
+impl MegaScreen {
+    /// The picture last shown with `present`, one ARGB value per pixel in
+    /// rows of `MEGA_WIDTH`.
+    pub fn frame(&self) -> &[u32] {
+        &self.front
+    }
+
+    /// Unsigned 8-bit samples of digitized sound played during the last
+    /// frame, and their sample rate.
+    pub fn sound(&self) -> Option<(&[u8], u32)> {
+        (!self.played.is_empty()).then_some((&self.played[..], self.played_rate))
+    }
+
+    /// Shows the back buffer, faded by the screen alpha, and clears it.
+    pub(crate) fn present(&mut self) {
+        let alpha = self.alpha as u32;
+        for (shown, &pixel) in self.front.iter_mut().zip(&self.back) {
+            let channel = |shift: u32| (((pixel >> shift) & 0xFF) * alpha / 255) << shift;
+            *shown = 0xFF00_0000 | channel(16) | channel(8) | channel(0);
+        }
+        self.back.fill(0);
+        self.indices.fill(0);
+    }
+
+    /// Blanks everything, for switching in or out of MegaChip mode.
+    pub(crate) fn clear(&mut self) {
+        self.back.fill(0);
+        self.front.fill(0);
+        self.indices.fill(0);
+    }
+
+    /// Loads `count` ARGB colours from memory at `addr` into palette
+    /// entries 1 onwards; entry 0 stays transparent.
+    pub(crate) fn load_palette(&mut self, ram: &[u8], addr: usize, count: usize) {
+        for (index, color) in self.palette[1..=count].iter_mut().enumerate() {
+            let bytes: [u8; 4] = std::array::from_fn(|i| ram[(addr + index * 4 + i) % ram.len()]);
+            *color = u32::from_be_bytes(bytes);
+        }
+    }
+
+    /// Sets the size of sprites drawn from palette indices; 0 means 256.
+    pub(crate) fn set_sprite_size(&mut self, width: Option<u8>, height: Option<u8>) {
+        let size = |value: u8| if value == 0 { 256 } else { value as usize };
+        if let Some(width) = width {
+            self.sprite_width = size(width);
+        }
+        if let Some(height) = height {
+            self.sprite_height = size(height);
+        }
+    }
+
+    pub(crate) fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    /// Sets the blend mode from its 080N code, ignoring unknown ones.
+    pub(crate) fn set_blend(&mut self, code: u8) {
+        if let Some(blend) = Blend::from_code(code) {
+            self.blend = blend;
+        }
+    }
+
+    pub(crate) fn set_collision_color(&mut self, index: u8) {
+        self.collision_color = index;
+    }
+
+    /// Draws a sprite of palette indices from `addr` with its top left at
+    /// (`x`, `y`), clipped at the edges. Index 0 is transparent. Returns
+    /// whether it drew over a pixel of the collision colour.
+    pub(crate) fn draw_sprite(&mut self, ram: &[u8], addr: usize, x: usize, y: usize) -> bool {
+        let (width, height) = (self.sprite_width, self.sprite_height);
+        let mut collided = false;
+
+        for row in 0..height.min(MEGA_HEIGHT.saturating_sub(y)) {
+            for column in 0..width.min(MEGA_WIDTH.saturating_sub(x)) {
+                let index = ram[(addr + row * width + column) % ram.len()];
+                if index == 0 {
+                    continue;
+                }
+                let pos = (y + row) * MEGA_WIDTH + x + column;
+                collided |= self.indices[pos] != 0 && self.indices[pos] == self.collision_color;
+                self.indices[pos] = index;
+                self.back[pos] = self
+                    .blend
+                    .apply(self.palette[index as usize], self.back[pos]);
+            }
+        }
+        collided
+    }
+
+    /// Draws a one-bit font sprite `width` pixels wide, which MegaChip
+    /// programs still use for text, in opaque white.
+    pub(crate) fn draw_font(
+        &mut self,
+        ram: &[u8],
+        addr: usize,
+        (x, y): (usize, usize),
+        (width, rows): (usize, usize),
+    ) -> bool {
+        let bytes_per_row = width / 8;
+        let mut collided = false;
+
+        for row in 0..rows.min(MEGA_HEIGHT.saturating_sub(y)) {
+            for column in 0..width.min(MEGA_WIDTH.saturating_sub(x)) {
+                let byte = ram[(addr + row * bytes_per_row + column / 8) % ram.len()];
+                if byte & (0x80 >> (column % 8)) == 0 {
+                    continue;
+                }
+                let pos = (y + row) * MEGA_WIDTH + x + column;
+                collided |= self.indices[pos] != 0 && self.indices[pos] == self.collision_color;
+                self.indices[pos] = 0xFF;
+                self.back[pos] = 0xFFFF_FFFF;
+            }
+        }
+        collided
+    }
+
+    /// Shifts the back buffer by the given number of pixels, filling
+    /// uncovered pixels with black.
+    pub(crate) fn scroll(&mut self, dx: isize, dy: isize) {
+        let (old_back, old_indices) = (self.back.clone(), self.indices.clone());
+        let (width, height) = (MEGA_WIDTH as isize, MEGA_HEIGHT as isize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let pos = (y * width + x) as usize;
+                if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    let src = (src_y * width + src_x) as usize;
+                    self.back[pos] = old_back[src];
+                    self.indices[pos] = old_indices[src];
+                } else {
+                    self.back[pos] = 0;
+                    self.indices[pos] = 0;
+                }
+            }
+        }
+    }
+
+    /// Starts the digitized sound whose header is at `addr`, replacing any
+    /// sound already playing.
+    pub(crate) fn play_sound(&mut self, ram: &[u8], addr: usize, looping: bool) {
+        let byte = |offset: usize| ram[(addr + offset) % ram.len()] as usize;
+        let rate = (byte(0) << 8 | byte(1)) as u32;
+        let len = byte(2) << 16 | byte(3) << 8 | byte(4);
+
+        self.sound = (rate > 0 && len > 0).then_some(Sound {
+            start: addr + SOUND_HEADER,
+            len,
+            rate,
+            looping,
+            ticks: 0,
+        });
+    }
+
+    pub(crate) fn stop_sound(&mut self) {
+        self.sound = None;
+    }
+
+    /// Plays one frame's worth of the digitized sound, if any.
+    pub(crate) fn end_frame(&mut self, ram: &[u8]) {
+        self.played.clear();
+        let Some(sound) = self.sound.as_mut() else {
+            return;
+        };
+
+        self.played_rate = sound.rate;
+        let from = (sound.ticks / FRAME_RATE as u64) as usize;
+        sound.ticks += sound.rate as u64;
+        let to = (sound.ticks / FRAME_RATE as u64) as usize;
+        for position in from..to {
+            if position >= sound.len && !sound.looping {
+                break;
+            }
+            let offset = sound.start + position % sound.len;
+            self.played.push(ram[offset % ram.len()]);
+        }
+
+        if to >= sound.len {
+            if sound.looping {
+                sound.ticks %= sound.len as u64 * FRAME_RATE as u64;
+            } else {
+                self.sound = None;
+            }
+        }
+    }
+
+    pub(crate) fn save(&self, encoder: &mut Encoder) {
+        encoder.bool(self.enabled);
+        for &color in &self.palette {
+            encoder.u32(color);
+        }
+        encoder.u16(self.sprite_width as u16);
+        encoder.u16(self.sprite_height as u16);
+        encoder.u8(self.alpha);
+        encoder.u8(self.blend.code());
+        encoder.u8(self.collision_color);
+        encoder.bytes(&self.indices);
+        for &pixel in self.back.iter().chain(&self.front) {
+            encoder.u32(pixel);
+        }
+        match &self.sound {
+            Some(sound) => {
+                encoder.bool(true);
+                encoder.u32(sound.start as u32);
+                encoder.u32(sound.len as u32);
+                encoder.u32(sound.rate);
+                encoder.bool(sound.looping);
+                encoder.u64(sound.ticks);
+            }
+            None => encoder.bool(false),
+        }
+    }
+
+    pub(crate) fn restore(&mut self, decoder: &mut Decoder) -> Result<(), Error> {
+        self.enabled = decoder.bool()?;
+        for color in self.palette.iter_mut() {
+            *color = decoder.u32()?;
+        }
+        self.sprite_width = decoder.u16()? as usize;
+        self.sprite_height = decoder.u16()? as usize;
+        self.alpha = decoder.u8()?;
+        self.blend =
+            Blend::from_code(decoder.u8()?).ok_or(Error::SaveState("Save state is corrupt"))?;
+        self.collision_color = decoder.u8()?;
+        self.indices.copy_from_slice(decoder.bytes(PIXELS)?);
+        for pixel in self.back.iter_mut().chain(self.front.iter_mut()) {
+            *pixel = decoder.u32()?;
+        }
+        self.sound = match decoder.bool()? {
+            true => Some(Sound {
+                start: decoder.u32()? as usize,
+                len: decoder.u32()? as usize,
+                rate: decoder.u32()?,
+                looping: decoder.bool()?,
+                ticks: decoder.u64()?,
+            }),
+            false => None,
+        };
+        // `play_sound` never starts an empty or silent sound, and playing
+        // one would divide by its length
+        if self
+            .sound
+            .as_ref()
+            .is_some_and(|sound| sound.len == 0 || sound.rate == 0)
+        {
+            return Err(Error::SaveState("Save state is corrupt"));
+        }
+        self.played.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save(screen: &MegaScreen) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        screen.save(&mut encoder);
+        encoder.finish()
+    }
+
+    #[test]
+    fn save_and_restore_round_trip() {
+        let mut ram = vec![0; 0x100];
+        // rate 8000Hz, 3 samples
+        ram[0x10..0x19].copy_from_slice(&[0x1F, 0x40, 0, 0, 3, 0, 0x80, 0x90, 0xA0]);
+        let mut screen = MegaScreen {
+            enabled: true,
+            ..MegaScreen::default()
+        };
+        screen.set_alpha(0x40);
+        screen.play_sound(&ram, 0x10, true);
+        screen.end_frame(&ram);
+
+        let mut restored = MegaScreen::default();
+        restored.restore(&mut Decoder::new(&save(&screen))).unwrap();
+        assert_eq!(save(&restored), save(&screen));
+    }
+
+    #[test]
+    fn restore_rejects_an_empty_sound() {
+        let screen = MegaScreen {
+            sound: Some(Sound {
+                start: 0,
+                len: 0,
+                rate: 8000,
+                looping: true,
+                ticks: 0,
+            }),
+            ..MegaScreen::default()
+        };
+
+        let mut restored = MegaScreen::default();
+        assert!(matches!(
+            restored.restore(&mut Decoder::new(&save(&screen))),
+            Err(Error::SaveState(_))
+        ));
+    }
+}
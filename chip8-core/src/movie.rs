@@ -0,0 +1,295 @@
+use std::fs;
+
+use crate::error::Error;
+use crate::processor::{InstructionSet, Processor};
+use crate::quirks::Quirks;
+use crate::savestate::{Decoder, Encoder, HASH_SIZE};
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const VERSION: u8 = 3;
+
+/// A recording of the keypad on every frame of a run, along with what else
+/// decides how the run goes: the ROM and where it was loaded, instruction
+/// set, quirks, speed and `RND` seed, and the machine state it started from
+/// if not power-on.
+///
+/// Starting a processor the same way and feeding it the recorded keypads
+/// with `Processor::set_keypad` reproduces the run frame for frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: [u8; HASH_SIZE],
+    pub isa: String,
+    pub load_address: u16,
+    pub quirks: Quirks,
+    pub ipf: usize,
+    pub rng: String,
+    pub seed: u64,
+    /// snapshot from `Processor::save_state` to start from
+    pub start_state: Option<Vec<u8>>,
+    // one key bitmask per frame, bit k for hex key k and bit 16 + k for
+    // key k of CHIP-8X's second keypad
+    frames: Vec<u32>,
+}
+
+impl Movie {
+    /// An empty recording of `chippy`, which has its ROM loaded and is about
+    /// to run its first recorded frame. `rng` and `seed` must be what its
+    /// generator was set up with. With `from_state` the recording starts
+    /// from `chippy`'s current state rather than from power-on.
+    pub fn new<T: InstructionSet>(
+        chippy: &Processor<T>,
+        rng: &str,
+        seed: u64,
+        from_state: bool,
+    ) -> Self {
+        Movie {
+            rom_hash: *chippy.rom_hash(),
+            isa: chippy.isa_name().to_string(),
+            load_address: chippy.start_address(),
+            quirks: chippy.get_quirks(),
+            ipf: chippy.get_ipf(),
+            rng: rng.to_string(),
+            seed,
+            start_state: from_state.then(|| chippy.save_state()),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Number of frames recorded.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Appends the keypads of the next frame.
+    pub fn push(&mut self, keypad: &[bool; 16], keypad2: &[bool; 16]) {
+        let mask = keypad
+            .iter()
+            .chain(keypad2)
+            .enumerate()
+            .filter(|&(_, &pressed)| pressed)
+            .fold(0, |mask, (key, _)| mask | 1 << key);
+        self.frames.push(mask);
+    }
+
+    /// Drops every frame from `len` on, for when recorded frames are undone.
+    pub fn truncate(&mut self, len: usize) {
+        self.frames.truncate(len);
+    }
+
+    /// The keypad recorded for `frame`, if the recording is that long.
+    pub fn keypad(&self, frame: usize) -> Option<[bool; 16]> {
+        let mask = *self.frames.get(frame)?;
+        Some(std::array::from_fn(|key| mask & 1 << key != 0))
+    }
+
+    /// The second keypad recorded for `frame`.
+    pub fn keypad2(&self, frame: usize) -> Option<[bool; 16]> {
+        let mask = *self.frames.get(frame)? >> 16;
+        Some(std::array::from_fn(|key| mask & 1 << key != 0))
+    }
+
+    /// Puts `chippy`, set up with this movie's instruction set, load
+    /// address, quirks, speed and generator and with its ROM loaded, where
+    /// the recording started.
+    pub fn start<T: InstructionSet>(&self, chippy: &mut Processor<T>) -> Result<(), Error> {
+        if chippy.rom_hash() != &self.rom_hash {
+            return Err(Error::Movie("Movie was recorded with a different ROM"));
+        }
+        if let Some(state) = &self.start_state {
+            chippy.load_state(state)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.bytes(MAGIC);
+        encoder.u8(VERSION);
+        encoder.bytes(&self.rom_hash);
+        encoder.str(&self.isa);
+        encoder.u16(self.load_address);
+        encoder.bytes(&self.quirks.encode());
+        encoder.u32(self.ipf as u32);
+        encoder.str(&self.rng);
+        encoder.u64(self.seed);
+        match &self.start_state {
+            Some(state) => {
+                encoder.bool(true);
+                encoder.u32(state.len() as u32);
+                encoder.bytes(state);
+            }
+            None => encoder.bool(false),
+        }
+        encoder.u32(self.frames.len() as u32);
+        for &mask in &self.frames {
+            encoder.u32(mask);
+        }
+
+        encoder.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let mut decoder = Decoder::new(data);
+
+        if decoder.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(Error::Movie("Not a movie file"));
+        }
+        if decoder.u8().ok() != Some(VERSION) {
+            return Err(Error::Movie("Unsupported movie version"));
+        }
+
+        // the decoder reports running out of data as a save state error
+        Self::decode(&mut decoder).map_err(|_| Error::Movie("Movie file is corrupt"))
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        let rom_hash = decoder.bytes(HASH_SIZE)?.try_into().unwrap();
+        let isa = decoder.str()?.to_string();
+        let load_address = decoder.u16()?;
+        let quirks =
+            Quirks::decode(decoder.bytes(7)?).ok_or(Error::Movie("Movie file is corrupt"))?;
+        let ipf = decoder.u32()? as usize;
+        let rng = decoder.str()?.to_string();
+        let seed = decoder.u64()?;
+        let start_state = match decoder.bool()? {
+            true => {
+                let len = decoder.u32()? as usize;
+                Some(decoder.bytes(len)?.to_vec())
+            }
+            false => None,
+        };
+        let frame_count = decoder.u32()? as usize;
+        let frames = (0..frame_count)
+            .map(|_| decoder.u32())
+            .collect::<Result<_, _>>()?;
+        if !decoder.is_empty() {
+            return Err(Error::Movie("Movie file is corrupt"));
+        }
+
+        Ok(Movie {
+            rom_hash,
+            isa,
+            load_address,
+            quirks,
+            ipf,
+            rng,
+            seed,
+            start_state,
+            frames,
+        })
+    }
+
+    pub fn write_file(&self, path: &str) -> Result<(), Error> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn read_file(path: &str) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::CosmacVip;
+
+    const ROM: [u8; 4] = [0x60, 0x01, 0x12, 0x02];
+
+    fn processor() -> Processor<CosmacVip> {
+        let mut chippy = Processor::new(CosmacVip, Quirks::VIP);
+        chippy.load_rom(&ROM).unwrap();
+        chippy
+    }
+
+    /// A movie of `processor` with a few frames of keys, starting from its
+    /// state after a frame if `from_state`.
+    fn movie(from_state: bool) -> Movie {
+        let mut chippy = processor();
+        chippy.run_frame().unwrap();
+        let mut movie = Movie::new(&chippy, "vip", 0x1234_5678_9ABC, from_state);
+        let mut keypad = [false; 16];
+        let mut keypad2 = [false; 16];
+        for key in 0..3 {
+            keypad[key] = true;
+            keypad2[15 - key] = true;
+            movie.push(&keypad, &keypad2);
+        }
+        movie
+    }
+
+    #[test]
+    fn movies_decode_as_encoded() {
+        for from_state in [false, true] {
+            let movie = movie(from_state);
+            assert_eq!(movie.start_state.is_some(), from_state);
+            assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+        }
+        let decoded = Movie::from_bytes(&movie(true).to_bytes()).unwrap();
+        assert_eq!(decoded.seed, 0x1234_5678_9ABC);
+        assert_eq!(decoded.rng, "vip");
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded.keypad(1).unwrap()[..3], [true, true, false]);
+        assert_eq!(decoded.keypad2(1).unwrap()[13..], [false, true, true]);
+        assert_eq!(decoded.keypad(3), None);
+    }
+
+    #[test]
+    fn damaged_files_are_refused() {
+        let bytes = movie(true).to_bytes();
+        assert!(matches!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::Movie("Movie file is corrupt"))
+        ));
+        assert!(matches!(
+            Movie::from_bytes(&[bytes.as_slice(), &[0]].concat()),
+            Err(Error::Movie("Movie file is corrupt"))
+        ));
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            Movie::from_bytes(&bad_magic),
+            Err(Error::Movie("Not a movie file"))
+        ));
+        let mut old = bytes;
+        old[MAGIC.len()] = VERSION - 1;
+        assert!(matches!(
+            Movie::from_bytes(&old),
+            Err(Error::Movie("Unsupported movie version"))
+        ));
+        assert!(matches!(
+            Movie::from_bytes(b"C8"),
+            Err(Error::Movie("Not a movie file"))
+        ));
+    }
+
+    #[test]
+    fn movies_start_only_on_their_own_rom() {
+        let movie = movie(true);
+        let mut chippy = processor();
+        movie.start(&mut chippy).unwrap();
+        assert_eq!(chippy.save_state(), *movie.start_state.as_ref().unwrap());
+
+        let mut other = Processor::new(CosmacVip, Quirks::VIP);
+        other.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(matches!(
+            movie.start(&mut other),
+            Err(Error::Movie("Movie was recorded with a different ROM"))
+        ));
+    }
+
+    #[test]
+    fn rewound_frames_are_dropped() {
+        let mut movie = movie(false);
+        movie.truncate(1);
+        assert_eq!(movie.len(), 1);
+        assert_eq!(movie.keypad(1), None);
+        movie.push(&[false; 16], &[false; 16]);
+        assert_eq!(movie.keypad(1), Some([false; 16]));
+        assert_eq!(movie.keypad(0).unwrap()[..2], [true, false]);
+    }
+}
@@ -0,0 +1,158 @@
+/// One instruction form, with its mnemonic in classic (Cowgod) and Octo
+/// syntax.
+///
+/// `pattern` spells the opcode in hex with letters standing for operand
+/// fields as in the `op_*` method names: `X` and `Y` register nibbles, `N`
+/// a nibble, `KK` a byte and `NNN` an address. The mnemonics mark where
+/// each field is written with `{x}`, `{y}`, `{n}`, `{kk}` and `{nnn}`, plus
+/// `{long}` for the address word following `F000` and `{far}` for
+/// MegaChip's 24-bit address, the `KK` byte of `01KK` followed by a word.
+/// The disassembler fills these in and the assembler matches against them,
+/// so both directions share a single definition of every instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub pattern: &'static str,
+    pub classic: &'static str,
+    pub octo: &'static str,
+    /// instruction length in bytes
+    pub size: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Classic,
+    Octo,
+}
+
+impl OpcodeInfo {
+    const fn new(pattern: &'static str, classic: &'static str, octo: &'static str) -> Self {
+        OpcodeInfo {
+            pattern,
+            classic,
+            octo,
+            size: 2,
+        }
+    }
+
+    /// A four-byte instruction, the opcode followed by an operand word.
+    const fn long(pattern: &'static str, classic: &'static str, octo: &'static str) -> Self {
+        OpcodeInfo {
+            size: 4,
+            ..Self::new(pattern, classic, octo)
+        }
+    }
+
+    /// Bits of the opcode fixed by the pattern, and their values.
+    pub fn mask_value(&self) -> (u16, u16) {
+        let mut mask = 0;
+        let mut value = 0;
+        for c in self.pattern.chars() {
+            mask <<= 4;
+            value <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                mask |= 0xF;
+                value |= digit as u16;
+            }
+        }
+        (mask, value)
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        let (mask, value) = self.mask_value();
+        opcode & mask == value
+    }
+
+    pub fn mnemonic(&self, syntax: Syntax) -> &'static str {
+        match syntax {
+            Syntax::Classic => self.classic,
+            Syntax::Octo => self.octo,
+        }
+    }
+}
+
+/// Every known instruction, CHIP-8 first, then SUPER-CHIP, XO-CHIP and the
+/// rarer variants. Where patterns overlap the more specific one comes
+/// first; where they are the same, as with `BNNN` and CHIP-8X's `BXYN`,
+/// the disassembler shows the first and the assembler accepts both.
+pub const OPCODES: &[OpcodeInfo] = &[
+    OpcodeInfo::new("00E0", "CLS", "clear"),
+    OpcodeInfo::new("00EE", "RET", "return"),
+    OpcodeInfo::new("1NNN", "JP {nnn}", "jump {nnn}"),
+    OpcodeInfo::new("2NNN", "CALL {nnn}", ":call {nnn}"),
+    OpcodeInfo::new("3XKK", "SE V{x}, {kk}", "if v{x} != {kk} then"),
+    OpcodeInfo::new("4XKK", "SNE V{x}, {kk}", "if v{x} == {kk} then"),
+    OpcodeInfo::new("5XY0", "SE V{x}, V{y}", "if v{x} != v{y} then"),
+    OpcodeInfo::new("6XKK", "LD V{x}, {kk}", "v{x} := {kk}"),
+    OpcodeInfo::new("7XKK", "ADD V{x}, {kk}", "v{x} += {kk}"),
+    OpcodeInfo::new("8XY0", "LD V{x}, V{y}", "v{x} := v{y}"),
+    OpcodeInfo::new("8XY1", "OR V{x}, V{y}", "v{x} |= v{y}"),
+    OpcodeInfo::new("8XY2", "AND V{x}, V{y}", "v{x} &= v{y}"),
+    OpcodeInfo::new("8XY3", "XOR V{x}, V{y}", "v{x} ^= v{y}"),
+    OpcodeInfo::new("8XY4", "ADD V{x}, V{y}", "v{x} += v{y}"),
+    OpcodeInfo::new("8XY5", "SUB V{x}, V{y}", "v{x} -= v{y}"),
+    OpcodeInfo::new("8XY6", "SHR V{x}, V{y}", "v{x} >>= v{y}"),
+    OpcodeInfo::new("8XY7", "SUBN V{x}, V{y}", "v{x} =- v{y}"),
+    OpcodeInfo::new("8XYE", "SHL V{x}, V{y}", "v{x} <<= v{y}"),
+    OpcodeInfo::new("9XY0", "SNE V{x}, V{y}", "if v{x} == v{y} then"),
+    OpcodeInfo::new("ANNN", "LD I, {nnn}", "i := {nnn}"),
+    OpcodeInfo::new("BNNN", "JP V0, {nnn}", "jump0 {nnn}"),
+    OpcodeInfo::new("CXKK", "RND V{x}, {kk}", "v{x} := random {kk}"),
+    OpcodeInfo::new("DXYN", "DRW V{x}, V{y}, {n}", "sprite v{x} v{y} {n}"),
+    OpcodeInfo::new("EX9E", "SKP V{x}", "if v{x} -key then"),
+    OpcodeInfo::new("EXA1", "SKNP V{x}", "if v{x} key then"),
+    OpcodeInfo::new("FX07", "LD V{x}, DT", "v{x} := delay"),
+    OpcodeInfo::new("FX0A", "LD V{x}, K", "v{x} := key"),
+    OpcodeInfo::new("FX15", "LD DT, V{x}", "delay := v{x}"),
+    OpcodeInfo::new("FX18", "LD ST, V{x}", "buzzer := v{x}"),
+    OpcodeInfo::new("FX1E", "ADD I, V{x}", "i += v{x}"),
+    OpcodeInfo::new("FX29", "LD F, V{x}", "i := hex v{x}"),
+    OpcodeInfo::new("FX33", "LD B, V{x}", "bcd v{x}"),
+    OpcodeInfo::new("FX55", "LD [I], V{x}", "save v{x}"),
+    OpcodeInfo::new("FX65", "LD V{x}, [I]", "load v{x}"),
+    // SUPER-CHIP
+    OpcodeInfo::new("00CN", "SCD {n}", "scroll-down {n}"),
+    OpcodeInfo::new("00FB", "SCR", "scroll-right"),
+    OpcodeInfo::new("00FC", "SCL", "scroll-left"),
+    OpcodeInfo::new("00FD", "EXIT", "exit"),
+    OpcodeInfo::new("00FE", "LOW", "lores"),
+    OpcodeInfo::new("00FF", "HIGH", "hires"),
+    OpcodeInfo::new("FX30", "LD HF, V{x}", "i := bighex v{x}"),
+    OpcodeInfo::new("FX75", "LD R, V{x}", "saveflags v{x}"),
+    OpcodeInfo::new("FX85", "LD V{x}, R", "loadflags v{x}"),
+    // XO-CHIP
+    OpcodeInfo::new("00DN", "SCU {n}", "scroll-up {n}"),
+    OpcodeInfo::new("5XY2", "SAVE V{x}, V{y}", "save v{x} - v{y}"),
+    OpcodeInfo::new("5XY3", "LOAD V{x}, V{y}", "load v{x} - v{y}"),
+    OpcodeInfo::long("F000", "LD I, LONG {long}", "i := long {long}"),
+    OpcodeInfo::new("FX01", "PLANE {x}", "plane {x}"),
+    OpcodeInfo::new("F002", "AUDIO", "audio"),
+    OpcodeInfo::new("FX3A", "PITCH V{x}", "pitch := v{x}"),
+    // HIRES CHIP-8
+    OpcodeInfo::new("0230", "HCLS", "hires-clear"),
+    // CHIP-8X
+    OpcodeInfo::new("02A0", "BCK", "background"),
+    OpcodeInfo::new("5XY1", "ADDN V{x}, V{y}", "nibble-add v{x} v{y}"),
+    OpcodeInfo::new("BXYN", "COL V{x}, V{y}, {n}", "colour v{x} v{y} {n}"),
+    OpcodeInfo::new("EXF2", "SKP2 V{x}", "if v{x} -key2 then"),
+    OpcodeInfo::new("EXF5", "SKNP2 V{x}", "if v{x} key2 then"),
+    OpcodeInfo::new("FXF8", "OUT V{x}", "out v{x}"),
+    OpcodeInfo::new("FXFB", "IN V{x}", "v{x} := in"),
+    // MegaChip
+    OpcodeInfo::new("0010", "MEGAOFF", "megaoff"),
+    OpcodeInfo::new("0011", "MEGAON", "megaon"),
+    OpcodeInfo::new("00BN", "SCRU {n}", "mega-scroll-up {n}"),
+    OpcodeInfo::long("01KK", "LDHI I, {far}", "i := ldhi {far}"),
+    OpcodeInfo::new("02KK", "LDPAL {kk}", "palette {kk}"),
+    OpcodeInfo::new("03KK", "SPRW {kk}", "sprite-width {kk}"),
+    OpcodeInfo::new("04KK", "SPRH {kk}", "sprite-height {kk}"),
+    OpcodeInfo::new("05KK", "ALPHA {kk}", "alpha {kk}"),
+    OpcodeInfo::new("060N", "DIGISND {n}", "digisound {n}"),
+    OpcodeInfo::new("0700", "STOPSND", "stop-sound"),
+    OpcodeInfo::new("080N", "BMODE {n}", "blend {n}"),
+    OpcodeInfo::new("09KK", "CCOL {kk}", "collision {kk}"),
+];
+
+/// The instruction form an opcode decodes as, if any.
+pub fn lookup(opcode: u16) -> Option<&'static OpcodeInfo> {
+    OPCODES.iter().find(|info| info.matches(opcode))
+}
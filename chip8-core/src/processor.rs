@@ -0,0 +1,1788 @@
+use crate::error::Error;
+use crate::megachip::{MegaScreen, MEGA_HEIGHT, MEGA_WIDTH};
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::random::{self, RandomSource, SplitMix};
+use crate::romdb;
+use crate::savestate::{Decoder, Encoder, HASH_SIZE};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+
+pub const SCREEN_WIDTH: usize = 128;
+pub const SCREEN_HEIGHT: usize = 64;
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+
+pub const START_ADDRESS: u16 = 0x200;
+pub const DEFAULT_IPF: usize = 10;
+const FONTSET_START_ADDRESS: u16 = 0x50;
+const FONTSET_SPRITE_SIZE: u16 = 5;
+const HIRES_FONTSET_START_ADDRESS: u16 = 0xA0;
+const HIRES_FONTSET_SPRITE_SIZE: u16 = 10;
+const RAM: usize = 4096;
+const XO_RAM: usize = 65536;
+const MEGA_RAM: usize = 0x100_0000;
+const CHIP8X_START_ADDRESS: u16 = 0x300;
+const ETI660_START_ADDRESS: u16 = 0x600;
+// the VP-590 colours one byte of pixels on each row
+const COLOR_COLUMNS: usize = LORES_WIDTH / 8;
+const COLOR_ZONE_ROWS: usize = 4;
+// colour codes 02A0 steps the background through: blue, black, green, red
+const BACKGROUND_CYCLE: [u8; 4] = [2, 0, 4, 1];
+const DEFAULT_FOREGROUND: u8 = 1;
+
+const FONTSET_SIZE: usize = 80;
+
+const FONT_DATA: [u8; FONTSET_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const HIRES_FONTSET_SIZE: usize = 160;
+
+const HIRES_FONT_DATA: [u8; HIRES_FONTSET_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[derive(Debug)]
+pub struct Processor<T: InstructionSet> {
+    state: ProcessorState,
+    isa: T,
+    ipf: usize,
+    rom_hash: [u8; HASH_SIZE],
+    // where the ROM is copied and starts running
+    load_address: u16,
+    strict: bool,
+    // addresses of invalid opcodes already skipped in permissive mode
+    reported: HashSet<u16>,
+    // addresses and opcodes skipped since the last `take_ignored`
+    ignored: Vec<(u16, u16)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessorState {
+    v_reg: [u8; 16],
+    ram: Vec<u8>,
+    // 24 bits wide for MegaChip's LDHI, 16 elsewhere
+    i_reg: u32,
+    pc: u16,
+    stack: [u16; 16],
+    sp: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    keypad: [bool; 16],
+    // CHIP-8X's second keypad
+    keypad2: [bool; 16],
+    // keys that went down and came up since the last frame
+    keys_pressed: [bool; 16],
+    keys_released: [bool; 16],
+    // key pressed while FX0A waits, to be returned when released
+    awaited_key: Option<u8>,
+    screen: [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+    // size of the display outside high resolution mode
+    lores_size: (usize, usize),
+    colors: Option<ColorLayer>,
+    // MegaChip's colour display, which replaces the others when enabled
+    mega: Option<MegaScreen>,
+    hires: bool,
+    planes: u8,
+    rpl_flags: [u8; 16],
+    // XO-CHIP's audio pattern, once a ROM loads one
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    quirks: Quirks,
+    halted: bool,
+    vblank_wait: bool,
+    last_write: Option<(usize, usize)>,
+    display_stale: bool,
+    rng: Box<dyn RandomSource>,
+    // CHIP-8X I/O port 3: the last byte written and the next to be read
+    port_output: u8,
+    port_input: Option<u8>,
+}
+
+/// The VP-590 colour board's picture: a background colour and a foreground
+/// colour for lit pixels, set for each run of 8 pixels along a row.
+///
+/// Colours are 3-bit codes, bit 0 red, bit 1 blue and bit 2 green.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorLayer {
+    pub background: u8,
+    pub foreground: [[u8; COLOR_COLUMNS]; LORES_HEIGHT],
+}
+
+impl Default for ColorLayer {
+    fn default() -> Self {
+        ColorLayer {
+            background: 0,
+            foreground: [[DEFAULT_FOREGROUND; COLOR_COLUMNS]; LORES_HEIGHT],
+        }
+    }
+}
+
+impl ColorLayer {
+    /// Colour code of the pixel at (`x`, `y`), lit or not.
+    pub fn color_at(&self, x: usize, y: usize, lit: bool) -> u8 {
+        if lit {
+            self.foreground[y % LORES_HEIGHT][x / 8 % COLOR_COLUMNS]
+        } else {
+            self.background
+        }
+    }
+
+    /// 8-bit RGB of a colour code.
+    pub fn rgb(color: u8) -> (u8, u8, u8) {
+        let level = |bit: u8| if color & bit != 0 { 0xFF } else { 0x00 };
+        (level(0x01), level(0x04), level(0x02))
+    }
+}
+
+/// Operand fields of an opcode, named after its nibbles as in `0xIXYN`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fields {
+    pub nibbles: (u16, u16, u16, u16),
+    pub x: usize,
+    pub y: usize,
+    pub n: usize,
+    pub nnn: usize,
+    pub kk: u8,
+}
+
+impl Fields {
+    pub fn decode(opcode: u16) -> Self {
+        let b0 = (opcode & 0xF000) >> 12;
+        let b1 = (opcode & 0x0F00) >> 8;
+        let b2 = (opcode & 0x00F0) >> 4;
+        let b3 = opcode & 0x000F;
+
+        Fields {
+            nibbles: (b0, b1, b2, b3),
+            x: b1 as usize,
+            y: b2 as usize,
+            n: b3 as usize,
+            nnn: (opcode & 0x0FFF) as usize,
+            kk: (opcode & 0x00FF) as u8,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SuperChip;
+#[derive(Debug)]
+pub struct CosmacVip;
+#[derive(Debug)]
+pub struct XoChip;
+/// The VIP's two-page HIRES CHIP-8 interpreter, with a 64x64 display.
+#[derive(Debug)]
+pub struct HiresChip;
+/// CHIP-8X, the VIP interpreter for the VP-590 colour board and VP-580
+/// second keypad.
+#[derive(Debug)]
+pub struct Chip8X;
+/// The ETI-660's CHIP-8, loaded at 0x600 with a 64x48 display.
+#[derive(Debug)]
+pub struct Eti660;
+/// MegaChip, SUPER-CHIP extended with a 256x192 colour mode, 24-bit
+/// addressing and digitized sound.
+#[derive(Debug)]
+pub struct MegaChip;
+pub trait InstructionSet {
+    /// Short name identifying the instruction set in save states.
+    fn name(&self) -> &'static str;
+
+    /// Bytes of memory available to programs.
+    fn ram_size(&self) -> usize {
+        RAM
+    }
+
+    /// Where programs are loaded and start running.
+    fn start_address(&self) -> u16 {
+        START_ADDRESS
+    }
+
+    /// Width and height of the display, or of its low resolution mode on
+    /// instruction sets that can switch to SUPER-CHIP's 128x64.
+    fn display_size(&self) -> (usize, usize) {
+        (LORES_WIDTH, LORES_HEIGHT)
+    }
+
+    /// Whether the display has a VP-590 colour layer.
+    fn has_color(&self) -> bool {
+        false
+    }
+
+    /// Whether the display can switch to MegaChip's 256x192 colour mode.
+    fn has_megachip(&self) -> bool {
+        false
+    }
+
+    /// Decodes and runs a single opcode.
+    fn execute(&self, cpu: &mut ProcessorState, opcode: u16) -> Result<(), Error> {
+        self.execute_chip8(cpu, opcode)
+    }
+
+    /// Decodes and runs an opcode from the SUPER-CHIP 1.1 instruction set,
+    /// falling back to the base CHIP-8 set.
+    fn execute_schip(&self, cpu: &mut ProcessorState, opcode: u16) -> Result<(), Error> {
+        let Fields {
+            nibbles, x, y, n, ..
+        } = Fields::decode(opcode);
+
+        match nibbles {
+            (0x00, 0, 0xC, _) => self.op_00cn(cpu, n),
+            (0x00, 0, 0xF, 0xB) => self.op_00fb(cpu),
+            (0x00, 0, 0xF, 0xC) => self.op_00fc(cpu),
+            (0x00, 0, 0xF, 0xD) => self.op_00fd(cpu),
+            (0x00, 0, 0xF, 0xE) => self.op_00fe(cpu),
+            (0x00, 0, 0xF, 0xF) => self.op_00ff(cpu),
+            (0x0D, _, _, 0x00) => self.op_dxy0(cpu, x, y),
+            (0x0F, _, 0x03, 0x00) => self.op_fx30(cpu, x),
+            (0x0F, _, 0x07, 0x05) => self.op_fx75(cpu, x),
+            (0x0F, _, 0x08, 0x05) => self.op_fx85(cpu, x),
+            (_, _, _, _) => self.execute_chip8(cpu, opcode)?,
+        }
+        Ok(())
+    }
+
+    /// Decodes and runs an opcode from the base CHIP-8 instruction set,
+    /// failing on anything else. Extended sets fall back to this for the
+    /// opcodes they share with the original interpreter.
+    fn execute_chip8(&self, cpu: &mut ProcessorState, opcode: u16) -> Result<(), Error> {
+        let Fields {
+            nibbles,
+            x,
+            y,
+            n,
+            nnn,
+            kk,
+        } = Fields::decode(opcode);
+
+        match nibbles {
+            (0x00, 0, 0xE, 0) => self.op_00e0(cpu),
+            (0x00, 0, 0xE, 0xE) => self.op_00ee(cpu)?,
+            (0x01, _, _, _) => self.op_1nnn(cpu, nnn),
+            (0x02, _, _, _) => self.op_2nnn(cpu, nnn)?,
+            (0x03, _, _, _) => self.op_3xkk(cpu, x, kk),
+            (0x04, _, _, _) => self.op_4xkk(cpu, x, kk),
+            (0x05, _, _, 0x00) => self.op_5xy0(cpu, x, y),
+            (0x06, _, _, _) => self.op_6xkk(cpu, x, kk),
+            (0x07, _, _, _) => self.op_7xkk(cpu, x, kk),
+            (0x08, _, _, 0x00) => self.op_8xy0(cpu, x, y),
+            (0x08, _, _, 0x01) => self.op_8xy1(cpu, x, y),
+            (0x08, _, _, 0x02) => self.op_8xy2(cpu, x, y),
+            (0x08, _, _, 0x03) => self.op_8xy3(cpu, x, y),
+            (0x08, _, _, 0x04) => self.op_8xy4(cpu, x, y),
+            (0x08, _, _, 0x05) => self.op_8xy5(cpu, x, y),
+            (0x08, _, _, 0x06) => self.op_8xy6(cpu, x, y),
+            (0x08, _, _, 0x0E) => self.op_8xye(cpu, x, y),
+            (0x08, _, _, 0x07) => self.op_8xy7(cpu, x, y),
+            (0x09, _, _, 0x00) => self.op_9xy0(cpu, x, y),
+            (0x0A, _, _, _) => self.op_annn(cpu, nnn),
+            (0x0B, _, _, _) => self.op_bnnn(cpu, nnn),
+            (0x0C, _, _, _) => self.op_cxkk(cpu, x, kk),
+            (0x0D, _, _, _) => self.op_dxyn(cpu, x, y, n),
+            (0x0E, _, 0x09, 0x0E) => self.op_ex9e(cpu, x),
+            (0x0E, _, 0x0A, 0x01) => self.op_exa1(cpu, x),
+            (0x0F, _, 0x00, 0x07) => self.op_fx07(cpu, x),
+            (0x0F, _, 0x00, 0x0A) => self.op_fx0a(cpu, x),
+            (0x0F, _, 0x01, 0x05) => self.op_fx15(cpu, x),
+            (0x0F, _, 0x01, 0x08) => self.op_fx18(cpu, x),
+            (0x0F, _, 0x01, 0x0E) => self.op_fx1e(cpu, x),
+            (0x0F, _, 0x02, 0x09) => self.op_fx29(cpu, x),
+            (0x0F, _, 0x03, 0x03) => self.op_fx33(cpu, x),
+            (0x0F, _, 0x05, 0x05) => self.op_fx55(cpu, x),
+            (0x0F, _, 0x06, 0x05) => self.op_fx65(cpu, x),
+            (_, _, _, _) => {
+                let pc = cpu.pc.wrapping_sub(2);
+                return Err(Error::InvalidOpcode { pc, opcode });
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips over the next instruction.
+    fn skip(&self, cpu: &mut ProcessorState) {
+        cpu.pc = cpu.pc.wrapping_add(2);
+    }
+
+    /// MEGAOFF: leave MegaChip mode for SUPER-CHIP's low resolution
+    fn op_0010(&self, cpu: &mut ProcessorState) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.enabled = false;
+            mega.clear();
+        }
+        cpu.hires = false;
+        cpu.clear_screen();
+    }
+
+    /// MEGAON: switch to MegaChip's 256x192 colour mode
+    fn op_0011(&self, cpu: &mut ProcessorState) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.enabled = true;
+            mega.clear();
+            cpu.display_stale = true;
+        }
+    }
+
+    /// SCU nibble: scroll the MegaChip display up n rows
+    fn op_00bn(&self, cpu: &mut ProcessorState, n: usize) {
+        cpu.scroll(0, -(n as isize));
+    }
+
+    /// SCD nibble: scroll the display down n rows
+    fn op_00cn(&self, cpu: &mut ProcessorState, n: usize) {
+        cpu.scroll(0, n as isize);
+    }
+
+    /// SCU nibble: scroll the selected planes up n rows
+    fn op_00dn(&self, cpu: &mut ProcessorState, n: usize) {
+        cpu.scroll(0, -(n as isize));
+    }
+
+    /// CLS: clear the display buffer
+    fn op_00e0(&self, cpu: &mut ProcessorState) {
+        cpu.clear_screen();
+    }
+
+    /// RET: return from subroutine
+    fn op_00ee(&self, cpu: &mut ProcessorState) -> Result<(), Error> {
+        if cpu.sp == 0 {
+            let pc = cpu.pc.wrapping_sub(2);
+            return Err(Error::StackUnderflow { pc });
+        }
+        cpu.sp -= 1;
+        cpu.pc = cpu.stack[cpu.sp as usize];
+        Ok(())
+    }
+
+    /// SCR: scroll the display right 4 pixels
+    fn op_00fb(&self, cpu: &mut ProcessorState) {
+        cpu.scroll(4, 0);
+    }
+
+    /// SCL: scroll the display left 4 pixels
+    fn op_00fc(&self, cpu: &mut ProcessorState) {
+        cpu.scroll(-4, 0);
+    }
+
+    /// EXIT: halt the interpreter
+    fn op_00fd(&self, cpu: &mut ProcessorState) {
+        cpu.halted = true;
+    }
+
+    /// LOW: switch to 64x32 low resolution mode
+    fn op_00fe(&self, cpu: &mut ProcessorState) {
+        cpu.hires = false;
+        cpu.clear_screen();
+    }
+
+    /// HIGH: switch to 128x64 high resolution mode
+    fn op_00ff(&self, cpu: &mut ProcessorState) {
+        cpu.hires = true;
+        cpu.clear_screen();
+    }
+
+    /// LDHI I, addr: load the 24-bit address made of nn and the 16 bits
+    /// following the opcode into the index register
+    fn op_01nn(&self, cpu: &mut ProcessorState, kk: u8) {
+        let pc = cpu.pc as usize;
+        let high_byte = cpu.ram[pc] as u32;
+        let low_byte = cpu.ram[(pc + 1) % cpu.ram.len()] as u32;
+        cpu.i_reg = (kk as u32) << 16 | high_byte << 8 | low_byte;
+        cpu.pc = cpu.pc.wrapping_add(2);
+    }
+
+    /// LDPAL nn: load nn ARGB colours from [I] into palette entries 1 to nn
+    fn op_02nn(&self, cpu: &mut ProcessorState, kk: u8) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.load_palette(&cpu.ram, cpu.i_reg as usize, kk as usize);
+        }
+    }
+
+    /// BCK: step the background to the next colour
+    fn op_02a0(&self, cpu: &mut ProcessorState) {
+        if let Some(colors) = cpu.colors.as_mut() {
+            let index = BACKGROUND_CYCLE
+                .iter()
+                .position(|&c| c == colors.background);
+            let next = index.map_or(0, |index| (index + 1) % BACKGROUND_CYCLE.len());
+            colors.background = BACKGROUND_CYCLE[next];
+            cpu.display_stale = true;
+        }
+    }
+
+    /// SPRW nn: set the width of MegaChip sprites, 0 for 256
+    fn op_03nn(&self, cpu: &mut ProcessorState, kk: u8) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.set_sprite_size(Some(kk), None);
+        }
+    }
+
+    /// SPRH nn: set the height of MegaChip sprites, 0 for 256
+    fn op_04nn(&self, cpu: &mut ProcessorState, kk: u8) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.set_sprite_size(None, Some(kk));
+        }
+    }
+
+    /// ALPHA nn: set the opacity the screen is shown at
+    fn op_05nn(&self, cpu: &mut ProcessorState, kk: u8) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.set_alpha(kk);
+        }
+    }
+
+    /// DIGISND n: play the digitized sound at [I], looping if n is 0
+    fn op_060n(&self, cpu: &mut ProcessorState, n: usize) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.play_sound(&cpu.ram, cpu.i_reg as usize, n == 0);
+        }
+    }
+
+    /// STOPSND: stop the digitized sound
+    fn op_0700(&self, cpu: &mut ProcessorState) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.stop_sound();
+        }
+    }
+
+    /// BMODE n: set how sprites blend with the screen: 0 normal, 1 at 25%,
+    /// 2 at 50%, 3 additive, 4 multiplied
+    fn op_080n(&self, cpu: &mut ProcessorState, n: usize) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.set_blend(n as u8);
+        }
+    }
+
+    /// CCOL nn: set the palette index that sprites collide with
+    fn op_09nn(&self, cpu: &mut ProcessorState, kk: u8) {
+        if let Some(mega) = cpu.mega.as_mut() {
+            mega.set_collision_color(kk);
+        }
+    }
+
+    /// JP addr: jump to nnn
+    fn op_1nnn(&self, cpu: &mut ProcessorState, nnn: usize) {
+        cpu.pc = nnn as u16;
+    }
+
+    /// CALL addr: call subroutine at nnn
+    fn op_2nnn(&self, cpu: &mut ProcessorState, nnn: usize) -> Result<(), Error> {
+        if cpu.sp as usize >= cpu.stack.len() {
+            let pc = cpu.pc.wrapping_sub(2);
+            return Err(Error::StackOverflow { pc });
+        }
+        cpu.stack[cpu.sp as usize] = cpu.pc;
+        cpu.sp += 1;
+        cpu.pc = nnn as u16;
+        Ok(())
+    }
+
+    /// SE Vx, byte: skip next instruction if Vx == kk
+    fn op_3xkk(&self, cpu: &mut ProcessorState, x: usize, kk: u8) {
+        if cpu.v_reg[x] == kk {
+            self.skip(cpu);
+        }
+    }
+
+    /// SNE Vx, byte: skip next instruction if Vx != kk
+    fn op_4xkk(&self, cpu: &mut ProcessorState, x: usize, kk: u8) {
+        if cpu.v_reg[x] != kk {
+            self.skip(cpu);
+        }
+    }
+
+    /// SE Vx, Vy: skip next instruction if Vx == Vy
+    fn op_5xy0(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        if cpu.v_reg[x] == cpu.v_reg[y] {
+            self.skip(cpu);
+        }
+    }
+
+    /// ADD Vx, Vy: add each nibble of Vy to the same nibble of Vx, dropping
+    /// carries
+    fn op_5xy1(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        let (vx, vy) = (cpu.v_reg[x], cpu.v_reg[y]);
+        let high = (vx & 0xF0).wrapping_add(vy & 0xF0);
+        let low = ((vx & 0x0F) + (vy & 0x0F)) & 0x0F;
+        cpu.v_reg[x] = high | low;
+    }
+
+    /// SAVE Vx - Vy: store registers Vx through Vy into memory starting at
+    /// [I], in either direction, leaving I unchanged
+    fn op_5xy2(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        for (offset, reg) in register_range(x, y).enumerate() {
+            cpu.write(cpu.i_reg as usize + offset, cpu.v_reg[reg]);
+        }
+    }
+
+    /// LOAD Vx - Vy: load registers Vx through Vy from memory starting at
+    /// [I], in either direction, leaving I unchanged
+    fn op_5xy3(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        for (offset, reg) in register_range(x, y).enumerate() {
+            let addr = (cpu.i_reg as usize + offset) % cpu.ram.len();
+            cpu.v_reg[reg] = cpu.ram[addr];
+        }
+    }
+
+    /// LD Vx, byte: set Vx = kk
+    fn op_6xkk(&self, cpu: &mut ProcessorState, x: usize, kk: u8) {
+        cpu.v_reg[x] = kk;
+    }
+
+    /// ADD Vx, byte: add kk to Vx
+    fn op_7xkk(&self, cpu: &mut ProcessorState, x: usize, kk: u8) {
+        cpu.v_reg[x] = cpu.v_reg[x].wrapping_add(kk);
+    }
+
+    /// LD Vx, Vy: set Vx = Vy
+    fn op_8xy0(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        cpu.v_reg[x] = cpu.v_reg[y];
+    }
+
+    /// OR Vx, Vy: set Vx = Vx OR Vy
+    fn op_8xy1(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        cpu.v_reg[x] |= cpu.v_reg[y];
+        if cpu.quirks.vf_reset {
+            cpu.v_reg[0x0F] = 0;
+        }
+    }
+
+    /// AND Vx, Vy: set Vx = Vx AND Vy
+    fn op_8xy2(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        cpu.v_reg[x] &= cpu.v_reg[y];
+        if cpu.quirks.vf_reset {
+            cpu.v_reg[0x0F] = 0;
+        }
+    }
+
+    /// XOR Vx, Vy: set Vx = Vx XOR Vy
+    fn op_8xy3(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        cpu.v_reg[x] ^= cpu.v_reg[y];
+        if cpu.quirks.vf_reset {
+            cpu.v_reg[0x0F] = 0;
+        }
+    }
+
+    /// ADD Vx, Vy: set Vx = Vx + Vy and set VF = carry bit
+    fn op_8xy4(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        let v_x = cpu.v_reg[x] as u16;
+        let v_y = cpu.v_reg[y] as u16;
+        let sum = v_x + v_y;
+        cpu.v_reg[x] = sum as u8;
+        cpu.v_reg[0x0F] = if sum > 0xFF { 1 } else { 0 };
+    }
+
+    /// SUB Vx, Vy: set Vx = Vx - Vy and set VF = ~(borrow bit)
+    fn op_8xy5(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        let v_x = cpu.v_reg[x] as u16;
+        let v_y = cpu.v_reg[y] as u16;
+        let subtract = cpu.v_reg[x].wrapping_sub(cpu.v_reg[y]);
+        cpu.v_reg[x] = subtract;
+        cpu.v_reg[0x0F] = if v_x > v_y { 1 } else { 0 };
+    }
+
+    /// SHR Vx: shift Vx one bit right, save shifted-out bit in VF
+    fn op_8xy6(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        let source = if cpu.quirks.shift { x } else { y };
+        let value = cpu.v_reg[source];
+        cpu.v_reg[x] = value >> 1;
+        cpu.v_reg[0x0F] = value & 0x01;
+    }
+
+    /// SUBN Vx, Vy: set Vx = Vy - Vx and set VF = ~(borrow bit)
+    fn op_8xy7(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        let v_x = cpu.v_reg[x] as u16;
+        let v_y = cpu.v_reg[y] as u16;
+        let subtract = cpu.v_reg[y].wrapping_sub(cpu.v_reg[x]);
+        cpu.v_reg[x] = subtract;
+        cpu.v_reg[0x0F] = if v_y > v_x { 1 } else { 0 };
+    }
+
+    /// SHL Vx: shift Vx one bit left, save shifted-out bit in VF
+    fn op_8xye(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        let source = if cpu.quirks.shift { x } else { y };
+        let value = cpu.v_reg[source];
+        cpu.v_reg[x] = value << 1;
+        cpu.v_reg[0x0F] = value >> 7;
+    }
+
+    // SNE Vx, Vy: skip if Vx != Vy
+    fn op_9xy0(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        if cpu.v_reg[x] != cpu.v_reg[y] {
+            self.skip(cpu);
+        }
+    }
+
+    // LD I, addr: load into index register
+    fn op_annn(&self, cpu: &mut ProcessorState, nnn: usize) {
+        cpu.i_reg = nnn as u32;
+    }
+
+    // JP addr: jump to instruction
+    fn op_bnnn(&self, cpu: &mut ProcessorState, nnn: usize) {
+        let offset = if cpu.quirks.jump_with_vx {
+            cpu.v_reg[nnn >> 8]
+        } else {
+            cpu.v_reg[0]
+        };
+        cpu.pc = (nnn + offset as usize) as u16;
+    }
+
+    /// COL Vx, Vy: colour a block of 8x4 pixel zones with Vy. The low
+    /// nibbles of Vx and Vx+1 give the first zone's column and row, their
+    /// high nibbles how many more zones the block spans.
+    fn op_bxy0(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        let (horizontal, vertical) = (cpu.v_reg[x], cpu.v_reg[(x + 1) % 16]);
+        let color = cpu.v_reg[y] & 0x07;
+        if let Some(colors) = cpu.colors.as_mut() {
+            let rows = colors.foreground.len();
+            for zone_y in 0..=(vertical >> 4) as usize {
+                let top = ((vertical & 0x0F) as usize + zone_y) * COLOR_ZONE_ROWS;
+                for zone_x in 0..=(horizontal >> 4) as usize {
+                    let column = ((horizontal & 0x0F) as usize + zone_x) % COLOR_COLUMNS;
+                    for row in top..top + COLOR_ZONE_ROWS {
+                        colors.foreground[row % rows][column] = color;
+                    }
+                }
+            }
+            cpu.display_stale = true;
+        }
+    }
+
+    /// COL Vx, Vy, nibble: colour n rows of the 8-pixel column holding
+    /// pixel (Vx, Vx+1) with Vy
+    fn op_bxyn(&self, cpu: &mut ProcessorState, x: usize, y: usize, n: usize) {
+        let column = cpu.v_reg[x] as usize / 8 % COLOR_COLUMNS;
+        let top = cpu.v_reg[(x + 1) % 16] as usize;
+        let color = cpu.v_reg[y] & 0x07;
+        if let Some(colors) = cpu.colors.as_mut() {
+            let rows = colors.foreground.len();
+            for row in top..top + n {
+                colors.foreground[row % rows][column] = color;
+            }
+            cpu.display_stale = true;
+        }
+    }
+
+    // RND Vx, byte: set Vx = random byte AND kk
+    fn op_cxkk(&self, cpu: &mut ProcessorState, x: usize, kk: u8) {
+        cpu.v_reg[x] = cpu.rng.next_byte(&cpu.ram) & kk;
+    }
+
+    // DRW Vx, Vy, nibble: draw sprite from I at x, y
+    fn op_dxyn(&self, cpu: &mut ProcessorState, x: usize, y: usize, n: usize) {
+        cpu.draw_sprite(x, y, n, 8);
+        if cpu.quirks.display_wait {
+            // the VIP interpreter waited for the vertical blank interrupt
+            // before drawing, limiting sprites to 60 per second
+            cpu.vblank_wait = true;
+        }
+    }
+
+    /// DRW Vx, Vy, 0: draw 16x16 sprite from I at x, y
+    fn op_dxy0(&self, cpu: &mut ProcessorState, x: usize, y: usize) {
+        cpu.draw_sprite(x, y, 16, 16);
+    }
+
+    // SKP Vx: skip instruction if key in Vx is depressed
+    fn op_ex9e(&self, cpu: &mut ProcessorState, x: usize) {
+        if cpu.keypad[cpu.v_reg[x] as usize & 0x0F] {
+            self.skip(cpu);
+        }
+    }
+
+    // SKNP Vx: skip instruction if key in Vx is not depressed
+    fn op_exa1(&self, cpu: &mut ProcessorState, x: usize) {
+        if !cpu.keypad[cpu.v_reg[x] as usize & 0x0F] {
+            self.skip(cpu);
+        }
+    }
+
+    // SKP2 Vx: skip instruction if key Vx of the second keypad is down
+    fn op_exf2(&self, cpu: &mut ProcessorState, x: usize) {
+        if cpu.keypad2[cpu.v_reg[x] as usize & 0x0F] {
+            self.skip(cpu);
+        }
+    }
+
+    // SKNP2 Vx: skip instruction if key Vx of the second keypad is up
+    fn op_exf5(&self, cpu: &mut ProcessorState, x: usize) {
+        if !cpu.keypad2[cpu.v_reg[x] as usize & 0x0F] {
+            self.skip(cpu);
+        }
+    }
+
+    /// LD I, long addr: load the 16-bit address following the opcode into
+    /// the index register
+    fn op_f000(&self, cpu: &mut ProcessorState) {
+        let pc = cpu.pc as usize;
+        let high_byte = cpu.ram[pc] as u32;
+        let low_byte = cpu.ram[(pc + 1) % cpu.ram.len()] as u32;
+        cpu.i_reg = (high_byte << 8) | low_byte;
+        cpu.pc = cpu.pc.wrapping_add(2);
+    }
+
+    /// PLANE n: select the drawing planes affected by drawing, clearing
+    /// and scrolling
+    fn op_fn01(&self, cpu: &mut ProcessorState, n: usize) {
+        cpu.planes = n as u8 & 0x03;
+    }
+
+    /// AUDIO: load the 16-byte audio pattern buffer from [I]
+    fn op_f002(&self, cpu: &mut ProcessorState) {
+        let mut pattern = [0; 16];
+        for (i, byte) in pattern.iter_mut().enumerate() {
+            *byte = cpu.ram[(cpu.i_reg as usize + i) % cpu.ram.len()];
+        }
+        cpu.audio_pattern = Some(pattern);
+    }
+
+    // LD Vx, DT: set Vx = delay timer
+    fn op_fx07(&self, cpu: &mut ProcessorState, x: usize) {
+        cpu.v_reg[x] = cpu.delay_timer;
+    }
+
+    // LD DT, Vx: set delay timer = Vx
+    fn op_fx15(&self, cpu: &mut ProcessorState, x: usize) {
+        cpu.delay_timer = cpu.v_reg[x];
+    }
+
+    // LD ST, Vx: set sound timer = Vx
+    fn op_fx18(&self, cpu: &mut ProcessorState, x: usize) {
+        cpu.sound_timer = cpu.v_reg[x];
+    }
+
+    // ADD I, Vx: add Vx to index register
+    fn op_fx1e(&self, cpu: &mut ProcessorState, x: usize) {
+        cpu.i_reg = cpu.i_reg.wrapping_add(cpu.v_reg[x] as u32);
+        if cpu.quirks.fx1e_overflow {
+            cpu.v_reg[0x0F] = if cpu.i_reg > 0x0FFF { 1 } else { 0 };
+        }
+    }
+
+    // LD Vx, K: block until a key is pressed and released, store it in Vx
+    fn op_fx0a(&self, cpu: &mut ProcessorState, x: usize) {
+        // like the VIP, only a fresh press counts, so a key still held from
+        // the last FX0A doesn't answer this one too
+        match cpu.awaited_key {
+            Some(key) if cpu.keys_released[key as usize] => {
+                cpu.v_reg[x] = key;
+                cpu.awaited_key = None;
+                return;
+            }
+            Some(_) => {}
+            None => {
+                cpu.awaited_key = cpu
+                    .keys_pressed
+                    .iter()
+                    .position(|&pressed| pressed)
+                    .map(|key| key as u8);
+            }
+        }
+        cpu.pc = cpu.pc.wrapping_sub(2);
+    }
+
+    // LD F, Vx: set index register to sprite for char Vx
+    fn op_fx29(&self, cpu: &mut ProcessorState, x: usize) {
+        cpu.i_reg = (FONTSET_START_ADDRESS + FONTSET_SPRITE_SIZE * cpu.v_reg[x] as u16) as u32;
+    }
+
+    /// LD HF, Vx: set index register to big sprite for char Vx
+    fn op_fx30(&self, cpu: &mut ProcessorState, x: usize) {
+        cpu.i_reg = (HIRES_FONTSET_START_ADDRESS
+            + HIRES_FONTSET_SPRITE_SIZE * (cpu.v_reg[x] & 0x0F) as u16) as u32;
+    }
+
+    // LD B, Vx: store binary-coded decimal conversion at [I], [I+1], [I+2]
+    fn op_fx33(&self, cpu: &mut ProcessorState, x: usize) {
+        let addr = cpu.i_reg as usize;
+        cpu.write(addr, cpu.v_reg[x] / 100);
+        cpu.write(addr + 1, (cpu.v_reg[x] / 10) % 10);
+        cpu.write(addr + 2, cpu.v_reg[x] % 10);
+    }
+
+    /// PITCH Vx: set the audio pattern playback rate
+    fn op_fx3a(&self, cpu: &mut ProcessorState, x: usize) {
+        cpu.pitch = cpu.v_reg[x];
+    }
+
+    // LD [I], Vx: store registers V0-Vx (inclusive) into memory starting at [I]
+    fn op_fx55(&self, cpu: &mut ProcessorState, x: usize) {
+        for i in 0..=x {
+            cpu.write(cpu.i_reg as usize + i, cpu.v_reg[i]);
+        }
+        cpu.increment_after_load_store(x);
+    }
+
+    // LD Vx, [I]: load registers V0-Vx (inclusive) from memoery starting at [I]
+    fn op_fx65(&self, cpu: &mut ProcessorState, x: usize) {
+        for i in 0..=x {
+            let addr = (cpu.i_reg as usize + i) % cpu.ram.len();
+            cpu.v_reg[i] = cpu.ram[addr];
+        }
+        cpu.increment_after_load_store(x);
+    }
+
+    /// LD R, Vx: store registers V0-Vx (inclusive) into RPL user flags
+    fn op_fx75(&self, cpu: &mut ProcessorState, x: usize) {
+        let count = (x + 1).min(cpu.rpl_flags.len());
+        cpu.rpl_flags[..count].copy_from_slice(&cpu.v_reg[..count]);
+    }
+
+    /// LD Vx, R: load registers V0-Vx (inclusive) from RPL user flags
+    fn op_fx85(&self, cpu: &mut ProcessorState, x: usize) {
+        let count = (x + 1).min(cpu.rpl_flags.len());
+        cpu.v_reg[..count].copy_from_slice(&cpu.rpl_flags[..count]);
+    }
+
+    /// OUT Vx: write Vx to output port 3
+    fn op_fxf8(&self, cpu: &mut ProcessorState, x: usize) {
+        cpu.port_output = cpu.v_reg[x];
+    }
+
+    /// IN Vx: block until a byte arrives on input port 3, store it in Vx
+    fn op_fxfb(&self, cpu: &mut ProcessorState, x: usize) {
+        match cpu.port_input.take() {
+            Some(value) => cpu.v_reg[x] = value,
+            None => cpu.pc = cpu.pc.wrapping_sub(2),
+        }
+    }
+}
+
+impl<T: InstructionSet> Processor<T> {
+    pub fn new(isa_variant: T, quirks: Quirks) -> Self {
+        let state = ProcessorState {
+            v_reg: [0; 16],
+            ram: vec![0; isa_variant.ram_size()],
+            i_reg: 0,
+            pc: isa_variant.start_address(),
+            stack: [0; 16],
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            keypad: [false; 16],
+            keypad2: [false; 16],
+            keys_pressed: [false; 16],
+            keys_released: [false; 16],
+            awaited_key: None,
+            screen: [[0; SCREEN_WIDTH]; SCREEN_HEIGHT],
+            lores_size: isa_variant.display_size(),
+            colors: isa_variant.has_color().then(ColorLayer::default),
+            mega: isa_variant.has_megachip().then(MegaScreen::default),
+            hires: false,
+            planes: 0x01,
+            rpl_flags: [0; 16],
+            audio_pattern: None,
+            pitch: 64,
+            quirks,
+            halted: false,
+            vblank_wait: false,
+            last_write: None,
+            display_stale: false,
+            rng: Box::new(SplitMix::new(random::time_seed())),
+            port_output: 0,
+            port_input: None,
+        };
+
+        let mut chip_8 = Self {
+            state,
+            ipf: DEFAULT_IPF,
+            rom_hash: [0; HASH_SIZE],
+            load_address: isa_variant.start_address(),
+            isa: isa_variant,
+            strict: false,
+            reported: HashSet::new(),
+            ignored: Vec::new(),
+        };
+
+        // load fonts into memory
+        let font_start = FONTSET_START_ADDRESS as usize;
+        chip_8.state.ram[font_start..font_start + FONTSET_SIZE].copy_from_slice(&FONT_DATA);
+        let hires_font_start = HIRES_FONTSET_START_ADDRESS as usize;
+        chip_8.state.ram[hires_font_start..hires_font_start + HIRES_FONTSET_SIZE]
+            .copy_from_slice(&HIRES_FONT_DATA);
+
+        chip_8
+    }
+
+    pub fn load(&mut self, path: &str) -> Result<(), Error> {
+        let mut rom_buffer = Vec::new();
+        File::open(path)?.read_to_end(&mut rom_buffer)?;
+        self.load_rom(&rom_buffer)
+    }
+
+    /// Copies a ROM image into memory at the load address.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let start = self.load_address as usize;
+        let capacity = self.state.ram.len().saturating_sub(start);
+        if rom.len() > capacity {
+            return Err(Error::RomTooLarge {
+                size: rom.len(),
+                capacity,
+            });
+        }
+        self.state.ram[start..start + rom.len()].copy_from_slice(rom);
+        self.rom_hash = romdb::hash(rom);
+
+        Ok(())
+    }
+
+    /// Loads ROMs at `addr` and starts running there, rather than at the
+    /// instruction set's start address. For programs built to run
+    /// elsewhere, or raw data to be examined. Call before loading.
+    pub fn set_load_address(&mut self, addr: u16) {
+        self.load_address = addr;
+        self.state.pc = addr;
+    }
+
+    /// Replaces the random number generator behind `RND`. The default is a
+    /// `SplitMix` seeded from the clock.
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.state.rng = rng;
+    }
+
+    /// Reseeds `RND` with the default generator, making runs repeatable.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.set_rng(Box::new(SplitMix::new(seed)));
+    }
+
+    /// Executes one instruction, unless halted or waiting for the display.
+    /// On an error the machine is left as it was before the instruction.
+    pub fn tick(&mut self) -> Result<(), Error> {
+        if self.state.halted || self.state.vblank_wait {
+            return Ok(());
+        }
+        let pc = self.state.pc;
+        if pc as usize + 1 >= self.state.ram.len() {
+            return Err(Error::PcOutOfRange { pc });
+        }
+        let opcode = self.get_opcode();
+        self.state.last_write = None;
+        self.state.pc = pc.wrapping_add(2);
+        match self.isa.execute(&mut self.state, opcode) {
+            Err(Error::InvalidOpcode { pc, opcode }) if !self.strict => {
+                // step over it as the original interpreters did, but note it
+                // once, since it usually means the wrong instruction set
+                if self.reported.insert(pc) {
+                    self.ignored.push((pc, opcode));
+                }
+                Ok(())
+            }
+            Err(err) => {
+                self.state.pc = pc;
+                Err(err)
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Runs one 60Hz frame: up to `ipf` instructions, cut short if the
+    /// interpreter halts or waits for the display, followed by a single
+    /// tick of the delay and sound timers.
+    pub fn run_frame(&mut self) -> Result<(), Error> {
+        for _ in 0..self.ipf {
+            if !self.ready() {
+                break;
+            }
+            self.tick()?;
+        }
+        self.end_frame();
+        Ok(())
+    }
+
+    /// Whether `tick` will execute an instruction, rather than sitting
+    /// halted or waiting for the display.
+    pub fn ready(&self) -> bool {
+        !self.state.halted && !self.state.vblank_wait
+    }
+
+    /// Finishes a frame driven one `tick` at a time, releasing the display
+    /// wait, forgetting the frame's key presses and releases and ticking the
+    /// timers.
+    pub fn end_frame(&mut self) {
+        self.state.vblank_wait = false;
+        self.state.keys_pressed = [false; 16];
+        self.state.keys_released = [false; 16];
+
+        if self.state.delay_timer > 0 {
+            self.state.delay_timer -= 1
+        }
+        if self.state.sound_timer > 0 {
+            self.state.sound_timer -= 1
+        }
+        if let Some(mega) = self.state.mega.as_mut() {
+            mega.end_frame(&self.state.ram);
+        }
+    }
+
+    /// Sets the number of instructions executed per frame.
+    pub fn set_ipf(&mut self, ipf: usize) {
+        self.ipf = ipf;
+    }
+
+    pub fn get_ipf(&self) -> usize {
+        self.ipf
+    }
+
+    /// The address and opcode of each invalid instruction skipped outside
+    /// strict mode since the last call, for the caller to report. Each
+    /// address is only listed the first time.
+    pub fn take_ignored(&mut self) -> Vec<(u16, u16)> {
+        std::mem::take(&mut self.ignored)
+    }
+
+    /// In strict mode `tick` fails on opcodes the instruction set doesn't
+    /// define; otherwise they are skipped, and the first one skipped at each
+    /// address is kept for `take_ignored`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    fn get_opcode(&mut self) -> u16 {
+        self.get_opcode_at(self.state.pc)
+    }
+
+    /// The two bytes at `addr` read as an opcode.
+    pub fn get_opcode_at(&self, addr: u16) -> u16 {
+        let ram = &self.state.ram;
+        let high_byte = ram[addr as usize % ram.len()] as u16;
+        let low_byte = ram[(addr as usize + 1) % ram.len()] as u16;
+        (high_byte << 8) | low_byte
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.state.pc
+    }
+
+    pub fn get_index(&self) -> u32 {
+        self.state.i_reg
+    }
+
+    pub fn get_registers(&self) -> &[u8; 16] {
+        &self.state.v_reg
+    }
+
+    /// The call stack, innermost return address last.
+    pub fn get_stack(&self) -> &[u16] {
+        &self.state.stack[..self.state.sp as usize]
+    }
+
+    pub fn get_sp(&self) -> u16 {
+        self.state.sp
+    }
+
+    /// The delay and sound timers.
+    pub fn get_timers(&self) -> (u8, u8) {
+        (self.state.delay_timer, self.state.sound_timer)
+    }
+
+    pub fn get_ram(&self) -> &[u8] {
+        &self.state.ram
+    }
+
+    /// Addresses written by the last instruction, as a half-open range.
+    pub fn get_last_write(&self) -> Option<(usize, usize)> {
+        self.state.last_write
+    }
+
+    pub fn display_stale(&mut self) -> bool {
+        let is_stale = self.state.display_stale;
+        self.state.display_stale = false;
+        is_stale
+    }
+
+    /// Sets the keys held for the coming frame. Keys that change from the
+    /// last call count as pressed or released during the frame.
+    pub fn set_keypad(&mut self, input: &[bool; 16]) {
+        let state = &mut self.state;
+        for (key, &down) in input.iter().enumerate() {
+            state.keys_pressed[key] |= down && !state.keypad[key];
+            state.keys_released[key] |= !down && state.keypad[key];
+        }
+        state.keypad.copy_from_slice(input);
+    }
+
+    /// Sets the keys held on CHIP-8X's second keypad.
+    pub fn set_keypad2(&mut self, input: &[bool; 16]) {
+        self.state.keypad2.copy_from_slice(input);
+    }
+
+    /// The last byte the program wrote to I/O port 3 with FXF8.
+    pub fn get_port_output(&self) -> u8 {
+        self.state.port_output
+    }
+
+    /// Hands a byte to I/O port 3 for FXFB to read, replacing any the
+    /// program hasn't read yet.
+    pub fn set_port_input(&mut self, value: u8) {
+        self.state.port_input = Some(value);
+    }
+
+    /// The display buffer; each pixel holds one bit per drawing plane.
+    pub fn get_screen(&self) -> &[[u8; SCREEN_WIDTH]; SCREEN_HEIGHT] {
+        &self.state.screen
+    }
+
+    /// The colours of the screen, on instruction sets with a colour board.
+    pub fn get_colors(&self) -> Option<&ColorLayer> {
+        self.state.colors.as_ref()
+    }
+
+    /// The MegaChip display as ARGB pixels in rows of `MEGA_WIDTH`, while
+    /// MegaChip mode is on. It replaces `get_screen` then.
+    pub fn get_framebuffer(&self) -> Option<&[u32]> {
+        self.state.mega_screen().map(MegaScreen::frame)
+    }
+
+    /// Unsigned 8-bit samples of MegaChip digitized sound played during
+    /// the last frame, and their sample rate.
+    pub fn get_digitized_sound(&self) -> Option<(&[u8], u32)> {
+        self.state.mega.as_ref()?.sound()
+    }
+
+    /// Width and height of the active display mode, in pixels.
+    pub fn get_resolution(&self) -> (usize, usize) {
+        (self.state.width(), self.state.height())
+    }
+
+    /// SHA-1 hash of the loaded ROM.
+    pub fn rom_hash(&self) -> &[u8; HASH_SIZE] {
+        &self.rom_hash
+    }
+
+    /// Name of the instruction set, as written in save states.
+    pub fn isa_name(&self) -> &'static str {
+        self.isa.name()
+    }
+
+    /// Where programs are loaded and start running: the instruction set's
+    /// start address unless changed with `set_load_address`.
+    pub fn start_address(&self) -> u16 {
+        self.load_address
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.state.quirks
+    }
+
+    /// Snapshots the complete machine, including the instruction set and
+    /// quirks it is running with.
+    pub fn save_state(&self) -> Vec<u8> {
+        let state = &self.state;
+        let mut encoder = Encoder::new();
+
+        encoder.str(self.isa.name());
+        encoder.bytes(&state.quirks.encode());
+        encoder.bytes(&state.v_reg);
+        encoder.u32(state.i_reg);
+        encoder.u16(state.pc);
+        for &addr in &state.stack {
+            encoder.u16(addr);
+        }
+        encoder.u16(state.sp);
+        encoder.u8(state.delay_timer);
+        encoder.u8(state.sound_timer);
+        for &key in state.keypad.iter().chain(&state.keypad2) {
+            encoder.bool(key);
+        }
+        encoder.bool(state.awaited_key.is_some());
+        encoder.u8(state.awaited_key.unwrap_or(0));
+        for row in &state.screen {
+            encoder.bytes(row);
+        }
+        if let Some(colors) = &state.colors {
+            encoder.u8(colors.background);
+            for row in &colors.foreground {
+                encoder.bytes(row);
+            }
+        }
+        if let Some(mega) = &state.mega {
+            mega.save(&mut encoder);
+        }
+        encoder.bool(state.hires);
+        encoder.u8(state.planes);
+        encoder.bytes(&state.rpl_flags);
+        encoder.bool(state.audio_pattern.is_some());
+        encoder.bytes(&state.audio_pattern.unwrap_or_default());
+        encoder.u8(state.pitch);
+        encoder.bool(state.halted);
+        encoder.bool(state.vblank_wait);
+        encoder.u8(state.port_output);
+        encoder.bool(state.port_input.is_some());
+        encoder.u8(state.port_input.unwrap_or(0));
+        encoder.str(state.rng.name());
+        state.rng.save(&mut encoder);
+        encoder.u32(state.ram.len() as u32);
+        encoder.bytes(&state.ram);
+
+        encoder.finish()
+    }
+
+    /// Restores a snapshot taken by `save_state`. The snapshot must come from
+    /// the same instruction set; its quirks replace the current ones.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut decoder = Decoder::new(data);
+
+        if decoder.str()? != self.isa.name() {
+            return Err(Error::SaveState(
+                "Save state was taken with a different instruction set",
+            ));
+        }
+
+        let mut state = ProcessorState {
+            quirks: Quirks::decode(decoder.bytes(7)?)
+                .ok_or(Error::SaveState("Save state is corrupt"))?,
+            ..self.state.clone()
+        };
+        state.v_reg.copy_from_slice(decoder.bytes(16)?);
+        state.i_reg = decoder.u32()?;
+        state.pc = decoder.u16()?;
+        for addr in state.stack.iter_mut() {
+            *addr = decoder.u16()?;
+        }
+        state.sp = decoder.u16()?;
+        if state.sp as usize > state.stack.len() {
+            return Err(Error::SaveState("Save state is corrupt"));
+        }
+        state.delay_timer = decoder.u8()?;
+        state.sound_timer = decoder.u8()?;
+        for key in state.keypad.iter_mut().chain(state.keypad2.iter_mut()) {
+            *key = decoder.bool()?;
+        }
+        let waiting = decoder.bool()?;
+        let awaited_key = decoder.u8()?;
+        if awaited_key > 0x0F {
+            return Err(Error::SaveState("Save state is corrupt"));
+        }
+        state.awaited_key = waiting.then_some(awaited_key);
+        for row in state.screen.iter_mut() {
+            row.copy_from_slice(decoder.bytes(SCREEN_WIDTH)?);
+        }
+        if let Some(colors) = state.colors.as_mut() {
+            colors.background = decoder.u8()?;
+            for row in colors.foreground.iter_mut() {
+                row.copy_from_slice(decoder.bytes(COLOR_COLUMNS)?);
+            }
+        }
+        if let Some(mega) = state.mega.as_mut() {
+            mega.restore(&mut decoder)?;
+        }
+        state.hires = decoder.bool()?;
+        state.planes = decoder.u8()?;
+        state.rpl_flags.copy_from_slice(decoder.bytes(16)?);
+        let has_pattern = decoder.bool()?;
+        let pattern = decoder.bytes(16)?;
+        state.audio_pattern = has_pattern.then(|| pattern.try_into().unwrap());
+        state.pitch = decoder.u8()?;
+        state.halted = decoder.bool()?;
+        state.vblank_wait = decoder.bool()?;
+        state.port_output = decoder.u8()?;
+        let port_ready = decoder.bool()?;
+        let port_input = decoder.u8()?;
+        state.port_input = port_ready.then_some(port_input);
+        state.rng = random::from_name(decoder.str()?, 0).ok_or(Error::SaveState(
+            "Save state uses an unknown random number generator",
+        ))?;
+        state.rng.restore(&mut decoder)?;
+        let ram_len = decoder.u32()? as usize;
+        if ram_len != state.ram.len() {
+            return Err(Error::SaveState("Save state memory size does not match"));
+        }
+        state.ram.copy_from_slice(decoder.bytes(ram_len)?);
+        if !decoder.is_empty() {
+            return Err(Error::SaveState("Save state is corrupt"));
+        }
+
+        state.display_stale = true;
+        self.state = state;
+        Ok(())
+    }
+
+    /// The XO-CHIP audio pattern: 128 bits, most significant first, played
+    /// in a loop while the sound timer runs. `None` until the ROM loads one,
+    /// when the buzzer sounds as usual.
+    pub fn get_audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.state.audio_pattern.as_ref()
+    }
+
+    /// Rate the audio pattern plays at, in bits per second, as set by
+    /// PITCH: 4000Hz at the default pitch of 64, doubling every 48 steps.
+    pub fn get_pattern_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.state.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Whether the sound timer is running and the buzzer should sound.
+    pub fn sound_active(&self) -> bool {
+        self.state.sound_timer > 0
+    }
+
+    pub fn halted(&self) -> bool {
+        self.state.halted
+    }
+}
+
+impl ProcessorState {
+    fn width(&self) -> usize {
+        if self.mega_screen().is_some() {
+            MEGA_WIDTH
+        } else if self.hires {
+            SCREEN_WIDTH
+        } else {
+            self.lores_size.0
+        }
+    }
+
+    fn height(&self) -> usize {
+        if self.mega_screen().is_some() {
+            MEGA_HEIGHT
+        } else if self.hires {
+            SCREEN_HEIGHT
+        } else {
+            self.lores_size.1
+        }
+    }
+
+    /// Stores a byte in memory, wrapping around at the end, and widens the
+    /// range of addresses written by the current instruction.
+    fn write(&mut self, addr: usize, value: u8) {
+        let addr = addr % self.ram.len();
+        self.ram[addr] = value;
+        self.last_write = match self.last_write {
+            Some((start, end)) => Some((start.min(addr), end.max(addr + 1))),
+            None => Some((addr, addr + 1)),
+        };
+    }
+
+    /// The MegaChip display, if MegaChip mode is on.
+    fn mega_screen(&self) -> Option<&MegaScreen> {
+        self.mega.as_ref().filter(|mega| mega.enabled)
+    }
+
+    /// Clears the selected drawing planes. In MegaChip mode this instead
+    /// shows the frame drawn since the last clear and starts a new one.
+    fn clear_screen(&mut self) {
+        if let Some(mega) = self.mega.as_mut().filter(|mega| mega.enabled) {
+            mega.present();
+            self.display_stale = true;
+            return;
+        }
+        for row in self.screen.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !self.planes;
+            }
+        }
+        self.display_stale = true;
+    }
+
+    /// XORs a `width`-pixel wide sprite of `rows` rows from I onto each
+    /// selected plane, setting VF if any lit pixel is turned off. Sprite
+    /// data for each plane follows the previous one in memory.
+    ///
+    /// In MegaChip mode sprites are drawn in colour at the size set by
+    /// SPRW and SPRH, except for the fonts, and VF is set on touching the
+    /// collision colour.
+    fn draw_sprite(&mut self, x: usize, y: usize, rows: usize, width: usize) {
+        if let Some(mega) = self.mega.as_mut().filter(|mega| mega.enabled) {
+            let origin = (self.v_reg[x] as usize, self.v_reg[y] as usize);
+            let addr = self.i_reg as usize;
+            let collided = if addr < START_ADDRESS as usize {
+                mega.draw_font(&self.ram, addr, origin, (width, rows))
+            } else {
+                mega.draw_sprite(&self.ram, addr, origin.0, origin.1)
+            };
+            self.v_reg[0x0F] = collided as u8;
+            self.display_stale = true;
+            return;
+        }
+        let (screen_width, screen_height) = (self.width(), self.height());
+        let bytes_per_row = width / 8;
+        let mut addr = self.i_reg as usize;
+
+        self.v_reg[0x0F] = 0;
+        for plane in (0..2).map(|i| 1u8 << i) {
+            if self.planes & plane == 0 {
+                continue;
+            }
+            for row in 0..rows {
+                let y_coord = (self.v_reg[y] as usize % screen_height) + row;
+                if y_coord >= screen_height && self.quirks.clip_sprites {
+                    addr += bytes_per_row;
+                    continue;
+                }
+                let y_coord = y_coord % screen_height;
+                let mut sprite = 0u16;
+                for _ in 0..bytes_per_row {
+                    sprite = (sprite << 8) | self.ram[addr % self.ram.len()] as u16;
+                    addr += 1;
+                }
+                for shift in 0..width {
+                    if (sprite >> (width - 1 - shift)) & 0x01 == 0 {
+                        continue;
+                    }
+                    let x_coord = (self.v_reg[x] as usize % screen_width) + shift;
+                    if x_coord >= screen_width && self.quirks.clip_sprites {
+                        continue;
+                    }
+                    let x_coord = x_coord % screen_width;
+                    if self.screen[y_coord][x_coord] & plane != 0 {
+                        self.v_reg[0x0F] = 1;
+                    }
+                    self.screen[y_coord][x_coord] ^= plane;
+                }
+            }
+        }
+        self.display_stale = true;
+    }
+
+    fn increment_after_load_store(&mut self, x: usize) {
+        let increment = match self.quirks.memory_increment {
+            MemoryIncrement::XPlusOne => x as u32 + 1,
+            MemoryIncrement::X => x as u32,
+            MemoryIncrement::Unchanged => 0,
+        };
+        self.i_reg = self.i_reg.wrapping_add(increment);
+    }
+
+    /// Shifts the selected planes of the active display area by the given
+    /// number of pixels, filling uncovered pixels with black.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        if let Some(mega) = self.mega.as_mut().filter(|mega| mega.enabled) {
+            mega.scroll(dx, dy);
+            self.display_stale = true;
+            return;
+        }
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let old = self.screen;
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let shifted = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    old[src_y as usize][src_x as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.screen[y as usize][x as usize];
+                *pixel = (*pixel & !self.planes) | (shifted & self.planes);
+            }
+        }
+        self.display_stale = true;
+    }
+}
+
+impl InstructionSet for SuperChip {
+    fn name(&self) -> &'static str {
+        "schip"
+    }
+
+    fn execute(&self, cpu: &mut ProcessorState, opcode: u16) -> Result<(), Error> {
+        self.execute_schip(cpu, opcode)
+    }
+}
+
+impl InstructionSet for CosmacVip {
+    fn name(&self) -> &'static str {
+        "vip"
+    }
+}
+
+impl InstructionSet for Chip8X {
+    fn name(&self) -> &'static str {
+        "chip8x"
+    }
+
+    // the colour and keypad routines push the interpreter past 0x200
+    fn start_address(&self) -> u16 {
+        CHIP8X_START_ADDRESS
+    }
+
+    fn has_color(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, cpu: &mut ProcessorState, opcode: u16) -> Result<(), Error> {
+        let Fields {
+            nibbles, x, y, n, ..
+        } = Fields::decode(opcode);
+
+        match nibbles {
+            (0x00, 0x02, 0x0A, 0x00) => self.op_02a0(cpu),
+            (0x05, _, _, 0x01) => self.op_5xy1(cpu, x, y),
+            (0x0B, _, _, 0x00) => self.op_bxy0(cpu, x, y),
+            (0x0B, _, _, _) => self.op_bxyn(cpu, x, y, n),
+            (0x0E, _, 0x0F, 0x02) => self.op_exf2(cpu, x),
+            (0x0E, _, 0x0F, 0x05) => self.op_exf5(cpu, x),
+            (0x0F, _, 0x0F, 0x08) => self.op_fxf8(cpu, x),
+            (0x0F, _, 0x0F, 0x0B) => self.op_fxfb(cpu, x),
+            (_, _, _, _) => self.execute_chip8(cpu, opcode)?,
+        }
+        Ok(())
+    }
+}
+
+impl InstructionSet for Eti660 {
+    fn name(&self) -> &'static str {
+        "eti660"
+    }
+
+    // the monitor and interpreter fill the memory below
+    fn start_address(&self) -> u16 {
+        ETI660_START_ADDRESS
+    }
+
+    fn display_size(&self) -> (usize, usize) {
+        (64, 48)
+    }
+}
+
+impl InstructionSet for HiresChip {
+    fn name(&self) -> &'static str {
+        "hires"
+    }
+
+    // the interpreter's second page takes 0x200-0x243
+    fn start_address(&self) -> u16 {
+        0x244
+    }
+
+    fn display_size(&self) -> (usize, usize) {
+        (64, 64)
+    }
+
+    fn execute(&self, cpu: &mut ProcessorState, opcode: u16) -> Result<(), Error> {
+        match opcode {
+            0x0230 => self.op_00e0(cpu),
+            _ => self.execute_chip8(cpu, opcode)?,
+        }
+        Ok(())
+    }
+}
+
+impl InstructionSet for XoChip {
+    fn name(&self) -> &'static str {
+        "xo"
+    }
+
+    fn ram_size(&self) -> usize {
+        XO_RAM
+    }
+
+    fn execute(&self, cpu: &mut ProcessorState, opcode: u16) -> Result<(), Error> {
+        let Fields {
+            nibbles, x, y, n, ..
+        } = Fields::decode(opcode);
+
+        match nibbles {
+            (0x00, 0, 0xD, _) => self.op_00dn(cpu, n),
+            (0x05, _, _, 0x02) => self.op_5xy2(cpu, x, y),
+            (0x05, _, _, 0x03) => self.op_5xy3(cpu, x, y),
+            (0x0F, 0, 0x00, 0x00) => self.op_f000(cpu),
+            (0x0F, _, 0x00, 0x01) => self.op_fn01(cpu, x),
+            (0x0F, 0, 0x00, 0x02) => self.op_f002(cpu),
+            (0x0F, _, 0x03, 0x0A) => self.op_fx3a(cpu, x),
+            (_, _, _, _) => self.execute_schip(cpu, opcode)?,
+        }
+        Ok(())
+    }
+
+    fn skip(&self, cpu: &mut ProcessorState) {
+        // F000 NNNN is the only four-byte instruction and is skipped whole
+        let pc = cpu.pc as usize;
+        let long = cpu.ram[pc] == 0xF0 && cpu.ram[(pc + 1) % cpu.ram.len()] == 0x00;
+        cpu.pc = cpu.pc.wrapping_add(if long { 4 } else { 2 });
+    }
+}
+
+impl InstructionSet for MegaChip {
+    fn name(&self) -> &'static str {
+        "mega"
+    }
+
+    fn ram_size(&self) -> usize {
+        MEGA_RAM
+    }
+
+    fn has_megachip(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, cpu: &mut ProcessorState, opcode: u16) -> Result<(), Error> {
+        let Fields { nibbles, n, kk, .. } = Fields::decode(opcode);
+
+        match nibbles {
+            (0x00, 0, 0x1, 0x0) => self.op_0010(cpu),
+            (0x00, 0, 0x1, 0x1) => self.op_0011(cpu),
+            (0x00, 0, 0xB, _) => self.op_00bn(cpu, n),
+            (0x00, 0x1, _, _) => self.op_01nn(cpu, kk),
+            (0x00, 0x2, _, _) => self.op_02nn(cpu, kk),
+            (0x00, 0x3, _, _) => self.op_03nn(cpu, kk),
+            (0x00, 0x4, _, _) => self.op_04nn(cpu, kk),
+            (0x00, 0x5, _, _) => self.op_05nn(cpu, kk),
+            (0x00, 0x6, 0x0, _) => self.op_060n(cpu, n),
+            (0x00, 0x7, 0x0, 0x0) => self.op_0700(cpu),
+            (0x00, 0x8, 0x0, _) => self.op_080n(cpu, n),
+            (0x00, 0x9, _, _) => self.op_09nn(cpu, kk),
+            (_, _, _, _) => self.execute_schip(cpu, opcode)?,
+        }
+        Ok(())
+    }
+
+    fn skip(&self, cpu: &mut ProcessorState) {
+        // LDHI carries the low 16 bits of its address in a second word
+        let long = cpu.ram[cpu.pc as usize] == 0x01;
+        cpu.pc = cpu.pc.wrapping_add(if long { 4 } else { 2 });
+    }
+}
+
+/// Registers from x to y inclusive, counting down if y < x.
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A processor with `rom` loaded, run for a frame with `keys` held.
+    fn run<T: InstructionSet>(isa: T, quirks: Quirks, rom: &[u8], keys: &[usize]) -> Processor<T> {
+        let mut chippy = Processor::new(isa, quirks);
+        chippy.load_rom(rom).unwrap();
+        chippy.seed_rng(0);
+        let mut keypad = [false; 16];
+        for &key in keys {
+            keypad[key] = true;
+        }
+        chippy.set_keypad(&keypad);
+        chippy.run_frame().unwrap();
+        chippy
+    }
+
+    #[test]
+    fn key_skips_use_the_low_nibble_of_vx() {
+        // V0 = 0x25; SKP V0 skips V1 = 1, SKNP V0 doesn't skip V2 = 1
+        let rom = [0x60, 0x25, 0xE0, 0x9E, 0x61, 0x01, 0xE0, 0xA1, 0x62, 0x01];
+        let chippy = run(CosmacVip, Quirks::VIP, &rom, &[5]);
+        assert_eq!(chippy.get_registers()[1], 0);
+        assert_eq!(chippy.get_registers()[2], 1);
+
+        let chippy = run(CosmacVip, Quirks::VIP, &rom, &[]);
+        assert_eq!(chippy.get_registers()[1], 1);
+        assert_eq!(chippy.get_registers()[2], 0);
+    }
+
+    #[test]
+    fn invalid_opcodes_are_skipped_and_listed_once() {
+        // an invalid 5XYN in a loop back to the start
+        let rom = [0x50, 0x01, 0x12, 0x00];
+        let mut chippy = run(CosmacVip, Quirks::VIP, &rom, &[]);
+        assert_eq!(chippy.take_ignored(), vec![(0x200, 0x5001)]);
+        chippy.run_frame().unwrap();
+        assert!(chippy.take_ignored().is_empty());
+
+        chippy.set_strict(true);
+        assert!(matches!(
+            chippy.tick(),
+            Err(Error::InvalidOpcode { pc: 0x200, .. })
+        ));
+    }
+
+    #[test]
+    fn save_states_restore_the_machine() {
+        // draws, loads an audio pattern, then counts up in V0 forever
+        let rom = [
+            0x60, 0x05, 0xA2, 0x00, 0xF0, 0x02, 0xD0, 0x05, 0x70, 0x01, 0x12, 0x08,
+        ];
+        let mut chippy = run(XoChip, Quirks::XO_CHIP, &rom, &[3]);
+        let saved = chippy.save_state();
+        let registers = *chippy.get_registers();
+
+        chippy.run_frame().unwrap();
+        assert_ne!(*chippy.get_registers(), registers);
+        chippy.load_state(&saved).unwrap();
+        assert_eq!(*chippy.get_registers(), registers);
+        assert!(chippy.get_audio_pattern().is_some());
+        assert_eq!(chippy.save_state(), saved);
+
+        assert!(chippy.load_state(&saved[..saved.len() - 1]).is_err());
+        let mut vip = Processor::new(CosmacVip, Quirks::VIP);
+        assert!(matches!(
+            vip.load_state(&saved),
+            Err(Error::SaveState(
+                "Save state was taken with a different instruction set"
+            ))
+        ));
+    }
+
+    #[test]
+    fn corrupt_stack_pointers_and_awaited_keys_are_refused() {
+        let chippy = Processor::new(CosmacVip, Quirks::VIP);
+        let saved = chippy.save_state();
+        // name, quirks, V0-VF, I, PC and the stack come before SP; the
+        // timers, both keypads and the waiting flag before the awaited key
+        let sp = 1 + CosmacVip.name().len() + 7 + 16 + 4 + 2 + 2 * 16;
+        let awaited_key = sp + 2 + 2 + 32 + 1;
+
+        let mut state = saved.clone();
+        state[sp] = 17;
+        let mut loaded = Processor::new(CosmacVip, Quirks::VIP);
+        assert!(matches!(
+            loaded.load_state(&state),
+            Err(Error::SaveState(_))
+        ));
+
+        let mut state = saved.clone();
+        state[awaited_key - 1] = 1;
+        state[awaited_key] = 0x10;
+        assert!(matches!(
+            loaded.load_state(&state),
+            Err(Error::SaveState(_))
+        ));
+        state[awaited_key] = 0x0F;
+        assert!(loaded.load_state(&state).is_ok());
+    }
+
+    #[test]
+    fn calls_past_the_top_of_the_stack_overflow() {
+        // a subroutine that calls itself
+        let mut chippy = Processor::new(CosmacVip, Quirks::VIP);
+        chippy.load_rom(&[0x22, 0x00]).unwrap();
+        chippy.set_ipf(17);
+        assert!(matches!(
+            chippy.run_frame(),
+            Err(Error::StackOverflow { pc: 0x200 })
+        ));
+    }
+}
@@ -0,0 +1,167 @@
+/// How FX55/FX65 leave the index register after a load or store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    /// I is advanced past the last register, as on the COSMAC VIP
+    XPlusOne,
+    /// I is advanced by x, off by one, as on CHIP-48 and SUPER-CHIP 1.0
+    X,
+    /// I is left unchanged, as on SUPER-CHIP 1.1
+    Unchanged,
+}
+
+/// Behaviours that differ between CHIP-8 interpreters for the same opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift Vx in place instead of shifting Vy into Vx
+    pub shift: bool,
+    /// effect of FX55/FX65 on the index register
+    pub memory_increment: MemoryIncrement,
+    /// BNNN behaves as BXNN, jumping to xnn + Vx instead of nnn + V0
+    pub jump_with_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to zero
+    pub vf_reset: bool,
+    /// sprites are clipped at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+    /// DXYN waits for the next vertical blank, limiting draws to 60 per second
+    pub display_wait: bool,
+    /// FX1E sets VF when I overflows past the 12-bit address space
+    pub fx1e_overflow: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const VIP: Quirks = Quirks {
+        shift: false,
+        memory_increment: MemoryIncrement::XPlusOne,
+        jump_with_vx: false,
+        vf_reset: true,
+        clip_sprites: true,
+        display_wait: true,
+        fx1e_overflow: false,
+    };
+
+    /// CHIP-48 for the HP-48 calculators.
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        memory_increment: MemoryIncrement::X,
+        jump_with_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+        fx1e_overflow: false,
+    };
+
+    /// SUPER-CHIP 1.0, which kept the CHIP-48 behaviours.
+    pub const SCHIP_1_0: Quirks = Quirks::CHIP_48;
+
+    /// SUPER-CHIP 1.1, which stopped FX55/FX65 from touching I.
+    pub const SCHIP_1_1: Quirks = Quirks {
+        memory_increment: MemoryIncrement::Unchanged,
+        ..Quirks::CHIP_48
+    };
+
+    /// XO-CHIP as implemented by Octo.
+    pub const XO_CHIP: Quirks = Quirks {
+        shift: false,
+        memory_increment: MemoryIncrement::XPlusOne,
+        jump_with_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false,
+        fx1e_overflow: false,
+    };
+
+    /// Packs the quirks into bytes for save states.
+    pub fn encode(&self) -> [u8; 7] {
+        let memory_increment = match self.memory_increment {
+            MemoryIncrement::XPlusOne => 0,
+            MemoryIncrement::X => 1,
+            MemoryIncrement::Unchanged => 2,
+        };
+        [
+            self.shift as u8,
+            memory_increment,
+            self.jump_with_vx as u8,
+            self.vf_reset as u8,
+            self.clip_sprites as u8,
+            self.display_wait as u8,
+            self.fx1e_overflow as u8,
+        ]
+    }
+
+    /// Unpacks quirks written by `encode`.
+    pub fn decode(bytes: &[u8]) -> Option<Quirks> {
+        if bytes.len() != 7 {
+            return None;
+        }
+        let memory_increment = match bytes[1] {
+            0 => MemoryIncrement::XPlusOne,
+            1 => MemoryIncrement::X,
+            2 => MemoryIncrement::Unchanged,
+            _ => return None,
+        };
+        Some(Quirks {
+            shift: bytes[0] != 0,
+            memory_increment,
+            jump_with_vx: bytes[2] != 0,
+            vf_reset: bytes[3] != 0,
+            clip_sprites: bytes[4] != 0,
+            display_wait: bytes[5] != 0,
+            fx1e_overflow: bytes[6] != 0,
+        })
+    }
+
+    /// Looks up a preset by the name used on the command line.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::VIP),
+            "chip48" => Some(Quirks::CHIP_48),
+            "schip10" => Some(Quirks::SCHIP_1_0),
+            "schip11" => Some(Quirks::SCHIP_1_1),
+            "xo" => Some(Quirks::XO_CHIP),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: [(&str, Quirks); 5] = [
+        ("vip", Quirks::VIP),
+        ("chip48", Quirks::CHIP_48),
+        ("schip10", Quirks::SCHIP_1_0),
+        ("schip11", Quirks::SCHIP_1_1),
+        ("xo", Quirks::XO_CHIP),
+    ];
+
+    #[test]
+    fn presets_are_found_by_name() {
+        for (name, quirks) in PRESETS {
+            assert_eq!(Quirks::preset(name), Some(quirks), "{}", name);
+        }
+        assert_eq!(Quirks::preset("octo"), None);
+    }
+
+    #[test]
+    fn every_flag_survives_encoding() {
+        let mut all = vec![Quirks {
+            memory_increment: MemoryIncrement::X,
+            fx1e_overflow: true,
+            ..Quirks::VIP
+        }];
+        all.extend(PRESETS.map(|(_, quirks)| quirks));
+        for quirks in all {
+            assert_eq!(Quirks::decode(&quirks.encode()), Some(quirks));
+        }
+    }
+
+    #[test]
+    fn bad_encodings_are_refused() {
+        let mut bytes = Quirks::VIP.encode();
+        bytes[1] = 3;
+        assert_eq!(Quirks::decode(&bytes), None);
+        assert_eq!(Quirks::decode(&Quirks::VIP.encode()[..6]), None);
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+
+const SKIP_BLOCK: usize = 64;
+// RAM copied into snapshots per frame, on average, above which snapshots
+// are taken less often
+const BYTES_PER_FRAME: usize = 0x100000;
+
+/// Ring buffer of recent save states for stepping backwards in time.
+///
+/// Only the newest snapshot is kept whole. Each older one is stored as the
+/// run-length encoded XOR against its successor, so frames where little
+/// changed (most of RAM, usually) cost a few bytes. Snapshots of machines
+/// with more than a megabyte of RAM, such as MegaChip's 16MiB, are taken
+/// every few frames rather than every frame, as taking one costs a copy of
+/// all of it.
+pub struct Rewind {
+    capacity: usize,
+    interval: usize,
+    // each snapshot with the frame it was taken at
+    latest: Option<(usize, Vec<u8>)>,
+    deltas: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl Rewind {
+    /// A buffer going back `frames` frames on a machine with `ram_size`
+    /// bytes of RAM. It keeps at least one snapshot, even if that goes back
+    /// further, unless `frames` is zero.
+    pub fn new(frames: usize, ram_size: usize) -> Self {
+        let interval = ram_size.div_ceil(BYTES_PER_FRAME).max(1);
+        let capacity = frames.div_ceil(interval);
+        Rewind {
+            capacity,
+            interval,
+            latest: None,
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Frames between snapshots.
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    /// Whether a snapshot should be pushed at `frame`.
+    pub fn due(&self, frame: usize) -> bool {
+        frame.is_multiple_of(self.interval)
+    }
+
+    /// Records a snapshot from `Processor::save_state` taken at `frame`,
+    /// forgetting the oldest one once the buffer is full.
+    pub fn push(&mut self, frame: usize, snapshot: Vec<u8>) {
+        if let Some((latest_frame, latest)) = self.latest.take() {
+            if latest.len() == snapshot.len() {
+                self.deltas
+                    .push_back((latest_frame, encode_delta(&latest, &snapshot)));
+            } else {
+                // a snapshot of a different machine can't be diffed against
+                self.deltas.clear();
+            }
+        }
+        if self.deltas.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+        self.latest = Some((frame, snapshot));
+    }
+
+    /// Steps back one snapshot, returning the one before the latest and
+    /// the frame it was taken at.
+    pub fn pop(&mut self) -> Option<(usize, Vec<u8>)> {
+        let (frame, delta) = self.deltas.pop_back()?;
+        let (latest_frame, latest) = self.latest.as_mut()?;
+        apply_delta(latest, &delta);
+        *latest_frame = frame;
+        Some((frame, latest.clone()))
+    }
+}
+
+/// Encodes `a XOR b` as alternating runs: a varint count of zero bytes, a
+/// varint count of literal bytes, then the literals.
+fn encode_delta(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < a.len() {
+        let zeros_start = i;
+        // skip unchanged memory a block at a time; MegaChip snapshots are
+        // over 16MiB, nearly all of it the same from frame to frame
+        while i + SKIP_BLOCK <= a.len() && a[i..i + SKIP_BLOCK] == b[i..i + SKIP_BLOCK] {
+            i += SKIP_BLOCK;
+        }
+        while i < a.len() && a[i] == b[i] {
+            i += 1;
+        }
+        let literal_start = i;
+        while i < a.len() && a[i] != b[i] {
+            i += 1;
+        }
+
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend((literal_start..i).map(|j| a[j] ^ b[j]));
+    }
+
+    out
+}
+
+/// XORs a delta from `encode_delta` into `data`, turning one side of the
+/// pair back into the other.
+fn apply_delta(data: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut i = 0;
+
+    while i < delta.len() {
+        pos += read_varint(delta, &mut i);
+        let literals = read_varint(delta, &mut i);
+        for byte in &delta[i..i + literals] {
+            data[pos] ^= byte;
+            pos += 1;
+        }
+        i += literals;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*i];
+        *i += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A snapshot that differs from its neighbours in a few places.
+    fn snapshot(frame: usize) -> Vec<u8> {
+        let mut data = vec![0; 1000];
+        data[frame] = frame as u8;
+        data[500] = frame as u8 * 3;
+        data[999] ^= frame as u8;
+        data
+    }
+
+    #[test]
+    fn popping_steps_back_through_the_snapshots() {
+        let mut rewind = Rewind::new(10, 4096);
+        for frame in 0..5 {
+            rewind.push(frame, snapshot(frame));
+        }
+        for frame in (0..4).rev() {
+            assert_eq!(rewind.pop(), Some((frame, snapshot(frame))));
+        }
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn the_oldest_snapshots_are_forgotten() {
+        let mut rewind = Rewind::new(3, 4096);
+        for frame in 0..10 {
+            rewind.push(frame, snapshot(frame));
+        }
+        for frame in (6..9).rev() {
+            assert_eq!(rewind.pop(), Some((frame, snapshot(frame))));
+        }
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn large_memories_are_snapshotted_less_often() {
+        let rewind = Rewind::new(600, 0x1000000);
+        assert_eq!(rewind.interval(), 16);
+        assert_eq!(rewind.capacity, 38);
+        assert!(rewind.due(32));
+        assert!(!rewind.due(33));
+        assert_eq!(Rewind::new(600, 4096).interval(), 1);
+    }
+
+    #[test]
+    fn short_windows_keep_a_snapshot() {
+        let mut rewind = Rewind::new(4, 0x1000000);
+        assert_eq!(rewind.capacity, 1);
+        rewind.push(0, snapshot(0));
+        rewind.push(16, snapshot(1));
+        assert_eq!(rewind.pop(), Some((0, snapshot(0))));
+        assert_eq!(Rewind::new(0, 0x1000000).capacity, 0);
+    }
+
+    #[test]
+    fn snapshots_of_another_machine_start_over() {
+        let mut rewind = Rewind::new(10, 4096);
+        rewind.push(0, snapshot(0));
+        rewind.push(1, snapshot(1));
+        rewind.push(2, vec![0; 10]);
+        assert_eq!(rewind.pop(), None);
+    }
+}
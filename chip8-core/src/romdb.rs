@@ -0,0 +1,669 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use crate::disasm;
+use crate::error::Error;
+use crate::processor::{Chip8X, Eti660, HiresChip, InstructionSet, START_ADDRESS};
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::savestate::HASH_SIZE;
+
+// the copy of the community database, see data/README.md
+const BUNDLED: &str = include_str!("../data/programs.json");
+
+/// What the database knows about one ROM. Anything left out is up to the
+/// command line or the usual defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: Option<String>,
+    /// instruction set name, as given to `--isa`
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+    /// instructions per frame
+    pub ipf: Option<usize>,
+    pub load_address: Option<u16>,
+    /// RGB colours for unlit pixels and each combination of lit planes
+    pub colors: Vec<u32>,
+    /// key bindings for the ROM, in keymap file syntax
+    pub keys: String,
+}
+
+/// Settings for known ROMs, looked up by the SHA-1 hash of the ROM image,
+/// so ROMs run on the right instruction set and quirks without being told.
+///
+/// The database is read from the `programs.json` of the community CHIP-8
+/// database (<https://github.com/chip-8/chip-8-database>): a list of
+/// programs, each with a `title` and a `roms` object keyed by the hex SHA-1
+/// of each ROM. A ROM runs on the first of its `platforms` an instruction
+/// set here covers, with that platform's `quirkyPlatforms` changes, and
+/// takes its `tickrate`, `startAddress`, `colors.pixels` and the `up`,
+/// `down`, `left`, `right`, `a` and `b` entries of `keys`. Other fields,
+/// and fields of the wrong type, are ignored.
+///
+/// ```text
+/// [{
+///   "title": "Nothing at all",
+///   "roms": {
+///     "da39a3ee5e6b4b0d3255bfef95601890afd80709": {
+///       "platforms": ["superchip"],
+///       "quirkyPlatforms": { "superchip": { "vblank": true } },
+///       "tickrate": 30,
+///       "colors": { "pixels": ["#000000", "#ffcc00"] },
+///       "keys": { "up": 5 }
+///     }
+///   }
+/// }]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomDatabase {
+    entries: HashMap<[u8; HASH_SIZE], RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut entries = HashMap::new();
+
+        for program in Parser::parse(text)?.items() {
+            let title = program.get("title").and_then(Json::as_str);
+            let roms = program.get("roms").map_or(&[][..], Json::members);
+            for (hash_text, line, rom) in roms {
+                let hash = parse_hash(hash_text).ok_or_else(|| Error::RomDatabase {
+                    line: *line,
+                    message: format!("'{}' is not a SHA-1 hash", hash_text),
+                })?;
+                let mut info = rom_info(rom);
+                info.title = title.map(str::to_string);
+                entries.insert(hash, info);
+            }
+        }
+
+        Ok(RomDatabase { entries })
+    }
+
+    pub fn read_file(path: &str) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// The copy of the community database built into the crate.
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("the bundled ROM database parses")
+    }
+
+    /// Adds the entries of `other`, replacing those for the same ROMs.
+    pub fn extend(&mut self, other: RomDatabase) {
+        self.entries.extend(other.entries);
+    }
+
+    /// What is known about the ROM with this SHA-1 hash.
+    pub fn lookup(&self, hash: &[u8; HASH_SIZE]) -> Option<&RomInfo> {
+        self.entries.get(hash)
+    }
+}
+
+/// A ROM image read for running, with its settings from the database or,
+/// for a ROM it doesn't know, a guess at its instruction set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rom {
+    pub data: Vec<u8>,
+    pub hash: [u8; HASH_SIZE],
+    pub info: RomInfo,
+    /// whether `info.platform` was guessed by `guess_platform`
+    pub guessed: bool,
+}
+
+impl Rom {
+    pub fn new(data: Vec<u8>, database: &RomDatabase) -> Self {
+        let hash = hash(&data);
+        let mut info = database.lookup(&hash).cloned().unwrap_or_default();
+        let guessed = info.platform.is_none();
+        if guessed {
+            info.platform = Some(guess_platform(&data).to_string());
+        }
+        Rom {
+            data,
+            hash,
+            info,
+            guessed,
+        }
+    }
+
+    pub fn read(path: &str, database: &RomDatabase) -> Result<Self, Error> {
+        Ok(Self::new(fs::read(path)?, database))
+    }
+}
+
+/// The settings in one ROM's database entry.
+fn rom_info(rom: &Json) -> RomInfo {
+    let mut info = RomInfo::default();
+
+    let platforms = rom.get("platforms").map_or(&[][..], Json::items);
+    if let Some((id, isa, mut quirks)) = platforms
+        .iter()
+        .filter_map(Json::as_str)
+        .find_map(|id| platform(id).map(|(isa, quirks)| (id, isa, quirks)))
+    {
+        let changes = rom.get("quirkyPlatforms").and_then(|quirky| quirky.get(id));
+        for (name, _, value) in changes.map_or(&[][..], Json::members) {
+            if let Json::Bool(on) = value {
+                set_quirk(&mut quirks, name, *on);
+            }
+        }
+        info.platform = Some(isa.to_string());
+        info.quirks = Some(quirks);
+    }
+
+    info.ipf = rom
+        .get("tickrate")
+        .and_then(Json::as_u64)
+        .map(|ipf| ipf as usize);
+    info.load_address = rom
+        .get("startAddress")
+        .and_then(Json::as_u64)
+        .and_then(|addr| u16::try_from(addr).ok());
+
+    let pixels = rom.get("colors").and_then(|colors| colors.get("pixels"));
+    let colors: Option<Vec<u32>> = pixels
+        .map_or(&[][..], Json::items)
+        .iter()
+        .map(|color| parse_color(color.as_str()?))
+        .collect();
+    info.colors = colors.unwrap_or_default();
+
+    // keymap lines, binding each CHIP-8 key to everything it is named for
+    let mut keys: BTreeMap<u64, Vec<&str>> = BTreeMap::new();
+    for (name, _, key) in rom.get("keys").map_or(&[][..], Json::members) {
+        if let (Some(bindings), Some(key @ 0..=15)) = (key_bindings(name), key.as_u64()) {
+            keys.entry(key).or_default().push(bindings);
+        }
+    }
+    for (key, bindings) in keys {
+        info.keys += &format!("{:x} = {}\n", key, bindings.join(", "));
+    }
+
+    info
+}
+
+/// The instruction set and quirks for a community database platform.
+fn platform(id: &str) -> Option<(&'static str, Quirks)> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some(("vip", Quirks::VIP)),
+        "modernChip8" => Some((
+            "vip",
+            Quirks {
+                vf_reset: false,
+                display_wait: false,
+                ..Quirks::VIP
+            },
+        )),
+        "chip48" => Some(("vip", Quirks::CHIP_48)),
+        "chip8x" => Some(("chip8x", Quirks::VIP)),
+        "superchip1" => Some(("schip", Quirks::SCHIP_1_0)),
+        "superchip" => Some(("schip", Quirks::SCHIP_1_1)),
+        "megachip8" => Some(("mega", Quirks::SCHIP_1_1)),
+        "xochip" => Some(("xo", Quirks::XO_CHIP)),
+        _ => None,
+    }
+}
+
+/// Turns a quirk, by its community database name, on or off.
+fn set_quirk(quirks: &mut Quirks, name: &str, on: bool) {
+    let mut set_increment = |increment: MemoryIncrement| {
+        if on {
+            quirks.memory_increment = increment;
+        } else if quirks.memory_increment == increment {
+            quirks.memory_increment = MemoryIncrement::XPlusOne;
+        }
+    };
+    match name {
+        "memoryIncrementByX" => set_increment(MemoryIncrement::X),
+        "memoryLeaveIUnchanged" => set_increment(MemoryIncrement::Unchanged),
+        "shift" => quirks.shift = on,
+        "wrap" => quirks.clip_sprites = !on,
+        "jump" => quirks.jump_with_vx = on,
+        "vblank" => quirks.display_wait = on,
+        "logic" => quirks.vf_reset = on,
+        _ => {}
+    }
+}
+
+/// Keymap file bindings for a community database key name.
+fn key_bindings(name: &str) -> Option<&'static str> {
+    match name {
+        "up" => Some("key Up, button dpup"),
+        "down" => Some("key Down, button dpdown"),
+        "left" => Some("key Left, button dpleft"),
+        "right" => Some("key Right, button dpright"),
+        "a" => Some("key Space, button a"),
+        "b" => Some("key Return, button b"),
+        _ => None,
+    }
+}
+
+/// An RGB colour written `#rrggbb`.
+fn parse_color(text: &str) -> Option<u32> {
+    let digits = text.strip_prefix('#')?;
+    if digits.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// Guesses the instruction set a ROM was written for, for ROMs the
+/// database doesn't know.
+///
+/// The ROM's code is found by following jumps and calls as the
+/// disassembler does, from each address an instruction set loads programs
+/// at. The load address that its jumps, calls and `I` loads point back into
+/// the ROM from most is taken to be the right one: 0x244 for HIRES CHIP-8,
+/// 0x300 for CHIP-8X and 0x600 for the ETI-660. A ROM loaded at 0x200 is
+/// MegaChip, XO-CHIP, CHIP-8X or SUPER-CHIP if its code uses opcodes only
+/// they have, otherwise the VIP's CHIP-8.
+pub fn guess_platform(rom: &[u8]) -> &'static str {
+    let origins = [
+        (START_ADDRESS, None),
+        (HiresChip.start_address(), Some(HiresChip.name())),
+        (Chip8X.start_address(), Some(Chip8X.name())),
+        (Eti660.start_address(), Some(Eti660.name())),
+    ];
+
+    let mut best = (0, Vec::new(), None);
+    for (origin, platform) in origins {
+        let opcodes = disasm::traced_opcodes(rom, origin);
+        let inside = origin as usize..origin as usize + rom.len();
+        let references = opcodes
+            .iter()
+            .filter(|&&(_, op)| matches!(op & 0xF000, 0x1000 | 0x2000 | 0xA000))
+            .filter(|&&(_, op)| inside.contains(&(op as usize & 0xFFF)))
+            .count();
+        // ties go to the earlier, more common load address
+        if best.1.is_empty() || references > best.0 {
+            best = (references, opcodes, platform);
+        }
+    }
+
+    let (_, opcodes, platform) = best;
+    if let Some(platform) = platform {
+        return platform;
+    }
+    let uses = |test: fn(u16) -> bool| opcodes.iter().any(|&(_, opcode)| test(opcode));
+
+    if uses(|op| op == 0x0011) && uses(|op| matches!(op >> 8, 0x01..=0x05)) {
+        "mega"
+    } else if uses(|op| {
+        matches!(op, 0xF000 | 0xF002)
+            || op & 0xF0FF == 0xF001
+            || matches!(op & 0xF00F, 0x5002 | 0x5003)
+            || op & 0xFFF0 == 0x00D0
+    }) {
+        "xo"
+    } else if uses(|op| {
+        op == 0x02A0
+            || op & 0xF00F == 0x5001
+            || matches!(op & 0xF0FF, 0xE0F2 | 0xE0F5 | 0xF0F8 | 0xF0FB)
+    }) {
+        "chip8x"
+    } else if uses(|op| {
+        matches!(op, 0x00FB..=0x00FF)
+            || op & 0xFFF0 == 0x00C0
+            || op & 0xF00F == 0xD000
+            || matches!(op & 0xF0FF, 0xF030 | 0xF075 | 0xF085)
+    }) {
+        "schip"
+    } else {
+        "vip"
+    }
+}
+
+/// SHA-1 hash of a ROM image, as the database and save states identify it.
+pub fn hash(rom: &[u8]) -> [u8; HASH_SIZE] {
+    sha1_smol::Sha1::from(rom).digest().bytes()
+}
+
+fn parse_hash(text: &str) -> Option<[u8; HASH_SIZE]> {
+    if text.len() != HASH_SIZE * 2 || !text.is_ascii() {
+        return None;
+    }
+    let mut hash = [0; HASH_SIZE];
+    for (byte, digits) in hash.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// A JSON value, as much of one as the database needs.
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// members in order, with the line each name is on
+    Object(Vec<(String, usize, Json)>),
+}
+
+impl Json {
+    fn get(&self, name: &str) -> Option<&Json> {
+        self.members()
+            .iter()
+            .find(|(member, _, _)| member == name)
+            .map(|(_, _, value)| value)
+    }
+
+    /// Members of an object, or none for anything else.
+    fn members(&self) -> &[(String, usize, Json)] {
+        match self {
+            Json::Object(members) => members,
+            _ => &[],
+        }
+    }
+
+    /// Items of an array, or none for anything else.
+    fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// A number that is a non-negative integer.
+    fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Json::Number(value) if value >= 0.0 && value.fract() == 0.0 => Some(value as u64),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(text: &'a str) -> Result<Json, Error> {
+        let mut parser = Parser {
+            text,
+            pos: 0,
+            line: 1,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(value),
+            Some(c) => Err(parser.error(format!("unexpected '{}' after the end", c))),
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::RomDatabase {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}', found the end", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some('a'..='z') => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_lowercase()) {
+                    self.next();
+                }
+                match &self.text[start..self.pos] {
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    "null" => Ok(Json::Null),
+                    word => Err(self.error(format!("unexpected '{}'", word))),
+                }
+            }
+            Some(c) => Err(self.error(format!("unexpected '{}'", c))),
+            None => Err(self.error(String::from("unexpected end"))),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, Error> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let line = self.line;
+            let name = self.string()?;
+            self.expect(':')?;
+            members.push((name, line, self.value()?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Json::Object(members)),
+                _ => return Err(self.error(String::from("expected ',' or '}'"))),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, Error> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err(self.error(String::from("expected ',' or ']'"))),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        if self.next() != Some('"') {
+            return Err(self.error(String::from("expected a string")));
+        }
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let digits = self.text.get(self.pos..self.pos + 4).unwrap_or("");
+                            let code = u32::from_str_radix(digits, 16)
+                                .map_err(|_| self.error(String::from("bad \\u escape")))?;
+                            self.pos += 4;
+                            // half a surrogate pair stands in for the
+                            // character it is part of
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        _ => return Err(self.error(String::from("bad escape in string"))),
+                    };
+                    out.push(c);
+                }
+                Some(c) => out.push(c),
+                None => return Err(self.error(String::from("unterminated string"))),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, Error> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.next();
+        }
+        let text = &self.text[start..self.pos];
+        text.parse()
+            .map(Json::Number)
+            .map_err(|_| self.error(format!("'{}' is not a number", text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    const ENTRY: &str = r##"[
+      {
+        "title": "Test \"ROM\"",
+        "roms": {
+          "da39a3ee5e6b4b0d3255bfef95601890afd80709": {
+            "platforms": ["chip8e", "superchip", "xochip"],
+            "quirkyPlatforms": {
+              "xochip": { "shift": true },
+              "superchip": { "vblank": true, "memoryLeaveIUnchanged": false }
+            },
+            "tickrate": 30,
+            "startAddress": 512,
+            "colors": { "pixels": ["#000000", "#FFCC00"], "buzzer": "#ffffff" },
+            "keys": { "up": 5, "a": 5, "down": 8, "player2Up": 1 }
+          }
+        }
+      }
+    ]"##;
+
+    /// Entries for the fixtures, kept out of the bundled database.
+    const FIXTURES: &str = include_str!("../tests/fixtures/programs.json");
+
+    fn fixture(name: &str, origin: u16) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/{}.8o", env!("CARGO_MANIFEST_DIR"), name);
+        assemble(&fs::read_to_string(path).unwrap(), origin).unwrap()
+    }
+
+    #[test]
+    fn entries_are_found_by_hash() {
+        let database = RomDatabase::parse(ENTRY).unwrap();
+        let info = database.lookup(&hash(&[])).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("Test \"ROM\""));
+        // the first platform run here, with its own quirk changes
+        assert_eq!(info.platform.as_deref(), Some("schip"));
+        assert_eq!(
+            info.quirks,
+            Some(Quirks {
+                display_wait: true,
+                memory_increment: MemoryIncrement::XPlusOne,
+                ..Quirks::SCHIP_1_1
+            })
+        );
+        assert_eq!(info.ipf, Some(30));
+        assert_eq!(info.load_address, Some(0x200));
+        assert_eq!(info.colors, vec![0x000000, 0xFFCC00]);
+        assert_eq!(
+            info.keys,
+            "5 = key Up, button dpup, key Space, button a\n8 = key Down, button dpdown\n"
+        );
+        assert_eq!(database.lookup(&hash(b"other")), None);
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        let bad_hash = "[{\"roms\": {\n\"abc\": {}}}]";
+        assert!(matches!(
+            RomDatabase::parse(bad_hash),
+            Err(Error::RomDatabase { line: 2, .. })
+        ));
+        let unterminated = "[\n{\"title\": \"x\"\n";
+        assert!(matches!(
+            RomDatabase::parse(unterminated),
+            Err(Error::RomDatabase { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn the_fixtures_are_found_by_hash() {
+        let mut database = RomDatabase::bundled();
+        database.extend(RomDatabase::parse(FIXTURES).unwrap());
+        for (name, origin, isa) in [
+            ("chip8x", 0x300, "chip8x"),
+            ("megachip", 0x200, "mega"),
+            ("planes", 0x200, "xo"),
+            ("hires", 0x200, "schip"),
+            ("vip_quirks", 0x200, "vip"),
+        ] {
+            let rom = Rom::new(fixture(name, origin), &database);
+            assert_eq!(rom.info.platform.as_deref(), Some(isa), "{}", name);
+            assert!(!rom.guessed, "{}", name);
+        }
+    }
+
+    #[test]
+    fn platforms_are_guessed_from_the_code() {
+        for (name, origin, isa) in [
+            ("chip8x", 0x300, "chip8x"),
+            ("eti660", 0x600, "eti660"),
+            ("hires_chip8", 0x244, "hires"),
+            ("hires", 0x200, "schip"),
+            ("megachip", 0x200, "mega"),
+            ("planes", 0x200, "xo"),
+            ("flags", 0x200, "vip"),
+        ] {
+            assert_eq!(guess_platform(&fixture(name, origin)), isa, "{}", name);
+        }
+    }
+
+    #[test]
+    fn data_is_not_taken_for_code() {
+        // an XO-CHIP `F000` in sprite data after a jump over it
+        let rom = [0x12, 0x04, 0xF0, 0x00, 0x12, 0x04];
+        assert_eq!(guess_platform(&rom), "vip");
+    }
+}
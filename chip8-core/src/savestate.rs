@@ -0,0 +1,224 @@
+use std::fs;
+
+use crate::error::Error;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 6;
+
+pub const HASH_SIZE: usize = 20;
+
+/// Appends fields of a snapshot to a byte buffer.
+#[derive(Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.buf.extend_from_slice(value);
+    }
+
+    /// Writes a length-prefixed string.
+    pub fn str(&mut self, value: &str) {
+        self.u8(value.len() as u8);
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads fields back in the order they were encoded.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Decoder { data }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(Error::SaveState("Save state is truncated"));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn str(&mut self) -> Result<&'a str, Error> {
+        let len = self.u8()? as usize;
+        std::str::from_utf8(self.bytes(len)?).map_err(|_| Error::SaveState("Save state is corrupt"))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Everything not yet read.
+    pub fn rest(self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// File the given save slot of a ROM is stored in.
+pub fn slot_path(rom_path: &str, slot: u8) -> String {
+    format!("{}.slot{}.state", rom_path, slot)
+}
+
+/// Writes a snapshot from `Processor::save_state` to disk, tagged with the
+/// hash of the ROM it was taken from.
+pub fn write_file(path: &str, rom_hash: &[u8; HASH_SIZE], state: &[u8]) -> Result<(), Error> {
+    let mut encoder = Encoder::new();
+    encoder.bytes(MAGIC);
+    encoder.u8(VERSION);
+    encoder.bytes(rom_hash);
+    encoder.bytes(state);
+
+    fs::write(path, encoder.finish())?;
+    Ok(())
+}
+
+/// Reads a snapshot written by `write_file`, refusing it if it was taken
+/// from a different ROM or by an incompatible version.
+pub fn read_file(path: &str, rom_hash: &[u8; HASH_SIZE]) -> Result<Vec<u8>, Error> {
+    let data = fs::read(path)?;
+    let mut decoder = Decoder::new(&data);
+
+    if decoder.bytes(MAGIC.len())? != MAGIC {
+        return Err(Error::SaveState("Not a save state file"));
+    }
+    if decoder.u8()? != VERSION {
+        return Err(Error::SaveState("Unsupported save state version"));
+    }
+    if decoder.bytes(HASH_SIZE)? != rom_hash {
+        return Err(Error::SaveState("Save state belongs to a different ROM"));
+    }
+
+    Ok(decoder.rest().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path in the temporary directory for this test.
+    fn temp_path(name: &str) -> String {
+        let dir = std::env::temp_dir();
+        let name = format!("chip8-{}-{}.state", std::process::id(), name);
+        dir.join(name).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn fields_decode_as_encoded() {
+        let mut encoder = Encoder::new();
+        encoder.u8(0xAB);
+        encoder.bool(true);
+        encoder.u16(0x1234);
+        encoder.u32(0x89AB_CDEF);
+        encoder.u64(u64::MAX - 1);
+        encoder.str("xoshiro");
+        encoder.bytes(&[1, 2, 3]);
+        let data = encoder.finish();
+
+        let mut decoder = Decoder::new(&data);
+        assert_eq!(decoder.u8().unwrap(), 0xAB);
+        assert!(decoder.bool().unwrap());
+        assert_eq!(decoder.u16().unwrap(), 0x1234);
+        assert_eq!(decoder.u32().unwrap(), 0x89AB_CDEF);
+        assert_eq!(decoder.u64().unwrap(), u64::MAX - 1);
+        assert_eq!(decoder.str().unwrap(), "xoshiro");
+        assert!(!decoder.is_empty());
+        assert_eq!(decoder.rest(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn reading_past_the_end_fails() {
+        let mut decoder = Decoder::new(&[1]);
+        assert!(matches!(
+            decoder.u16(),
+            Err(Error::SaveState("Save state is truncated"))
+        ));
+    }
+
+    #[test]
+    fn files_are_tied_to_their_rom() {
+        let path = temp_path("rom");
+        let hash = [7; HASH_SIZE];
+        write_file(&path, &hash, b"state").unwrap();
+
+        let read = read_file(&path, &hash);
+        let other = read_file(&path, &[8; HASH_SIZE]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), b"state");
+        assert!(matches!(
+            other,
+            Err(Error::SaveState("Save state belongs to a different ROM"))
+        ));
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let path = temp_path("version");
+        let hash = [7; HASH_SIZE];
+        write_file(&path, &hash, b"state").unwrap();
+        let mut data = fs::read(&path).unwrap();
+        data[MAGIC.len()] = VERSION - 1;
+        fs::write(&path, data).unwrap();
+
+        let read = read_file(&path, &hash);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            read,
+            Err(Error::SaveState("Unsupported save state version"))
+        ));
+    }
+}
@@ -0,0 +1,129 @@
+//! Golden-screen tests: each program in `tests/fixtures` is assembled, run
+//! for a fixed number of frames and its final screen compared against the
+//! text dump in `tests/golden`.
+//!
+//! Fixtures set how they run with `#:` comment lines: `#: isa vip`,
+//! `#: frames 30`, `#: seed 7`. Run with `BLESS=1` to write the current
+//! screens as the new goldens after checking they are right.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8_core::headless::{self, KeyScript, RunLimits};
+use chip8_core::{asm, dump};
+use chip8_core::{
+    Chip8X, CosmacVip, Eti660, HiresChip, InstructionSet, MegaChip, Processor, Quirks, SuperChip,
+    XoChip,
+};
+
+struct Fixture {
+    path: PathBuf,
+    isa: String,
+    frames: usize,
+    seed: u64,
+    source: String,
+}
+
+fn parse_fixture(path: &Path) -> Fixture {
+    let source = fs::read_to_string(path).unwrap();
+    let mut fixture = Fixture {
+        path: path.to_path_buf(),
+        isa: String::from("schip"),
+        frames: 60,
+        seed: 0,
+        source: String::new(),
+    };
+
+    for line in source.lines() {
+        let mut words = line
+            .strip_prefix("#:")
+            .unwrap_or_default()
+            .split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("isa"), Some(isa)) => fixture.isa = isa.to_string(),
+            (Some("frames"), Some(frames)) => fixture.frames = frames.parse().unwrap(),
+            (Some("seed"), Some(seed)) => fixture.seed = seed.parse().unwrap(),
+            (None, _) => {}
+            _ => panic!("{}: bad directive '{}'", path.display(), line),
+        }
+    }
+
+    fixture.source = source;
+    fixture
+}
+
+fn screen<T: InstructionSet>(mut chippy: Processor<T>, fixture: &Fixture) -> String {
+    // assembled where the instruction set loads it
+    let rom = asm::assemble(&fixture.source, chippy.start_address())
+        .unwrap_or_else(|err| panic!("{}: {}", fixture.path.display(), err));
+    chippy.load_rom(&rom).unwrap();
+    chippy.seed_rng(fixture.seed);
+
+    let limits = RunLimits {
+        frames: fixture.frames,
+        until_pc: None,
+        until_spin: false,
+    };
+    headless::run(&mut chippy, &limits, &KeyScript::default(), &mut |_| {}).unwrap();
+    dump::screen_text(&chippy)
+}
+
+fn run_fixture(fixture: &Fixture) -> String {
+    match fixture.isa.as_str() {
+        "vip" => screen(Processor::new(CosmacVip, Quirks::VIP), fixture),
+        "hires" => screen(Processor::new(HiresChip, Quirks::VIP), fixture),
+        "chip8x" => screen(Processor::new(Chip8X, Quirks::VIP), fixture),
+        "eti660" => screen(Processor::new(Eti660, Quirks::VIP), fixture),
+        "schip" => screen(Processor::new(SuperChip, Quirks::SCHIP_1_1), fixture),
+        "mega" => screen(Processor::new(MegaChip, Quirks::SCHIP_1_1), fixture),
+        "xo" => screen(Processor::new(XoChip, Quirks::XO_CHIP), fixture),
+        isa => panic!("unknown instruction set '{}'", isa),
+    }
+}
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+#[test]
+fn golden_screens() {
+    let bless = env::var_os("BLESS").is_some();
+    let mut fixtures: Vec<PathBuf> = fs::read_dir(tests_dir().join("fixtures"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "8o"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures found");
+
+    let mut failures = Vec::new();
+    for path in &fixtures {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let golden_path = tests_dir().join("golden").join(format!("{}.txt", name));
+        let actual = run_fixture(&parse_fixture(path));
+
+        if bless {
+            fs::write(&golden_path, &actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&golden_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => {
+                let row = expected
+                    .lines()
+                    .zip(actual.lines())
+                    .position(|(expected, actual)| expected != actual)
+                    .unwrap_or(0);
+                failures.push(format!(
+                    "{}: screen differs from row {}, got:\n{}",
+                    name, row, actual
+                ));
+            }
+            Err(_) => failures.push(format!("{}: no golden screen, run with BLESS=1", name)),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,261 @@
+#[cfg(feature = "sdl")]
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+#[cfg(feature = "sdl")]
+use chip8_core::Error;
+
+const SAMPLE_RATE: i32 = 44100;
+const FRAME_RATE: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" => Some(Waveform::Sawtooth),
+            _ => None,
+        }
+    }
+
+    /// Amplitude in [-1, 1] at `phase`, a fraction of one period.
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AudioSettings {
+    /// pitch of the beep in Hz
+    pub frequency: f32,
+    /// output level from 0.0 (silent) to 1.0 (full scale)
+    pub volume: f32,
+    pub waveform: Waveform,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+            muted: false,
+        }
+    }
+}
+
+/// Oscillator producing the beep while the sound timer is running, mixed
+/// with any MegaChip digitized sound queued for playback.
+pub struct Tone {
+    settings: AudioSettings,
+    sample_rate: f32,
+    phase: f32,
+    playing: bool,
+    // XO-CHIP audio pattern and its bit rate, played instead of the beep
+    pattern: Option<([u8; 16], f32)>,
+    digitized: VecDeque<f32>,
+}
+
+impl Tone {
+    pub fn new(settings: AudioSettings, sample_rate: i32) -> Self {
+        Tone {
+            settings,
+            sample_rate: sample_rate as f32,
+            phase: 0.0,
+            playing: false,
+            pattern: None,
+            digitized: VecDeque::new(),
+        }
+    }
+
+    /// Plays `pattern` in a loop at `rate` bits per second in place of the
+    /// beep, or goes back to the beep for `None`.
+    pub fn set_pattern(&mut self, pattern: Option<&[u8; 16]>, rate: f32) {
+        self.pattern = pattern.map(|pattern| (*pattern, rate));
+    }
+
+    /// Queues unsigned 8-bit samples at `rate` Hz, converted to the output
+    /// rate, to be mixed in after those already queued.
+    pub fn queue(&mut self, samples: &[u8], rate: u32) {
+        let count = (samples.len() as f32 * self.sample_rate / rate as f32) as usize;
+        self.digitized.extend((0..count).map(|i| {
+            let sample = samples[i * rate as usize / self.sample_rate as usize];
+            (sample as f32 - 128.0) / 128.0
+        }));
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        // the phase runs through one period of the beep, or once through
+        // all 128 bits of the pattern
+        let step = match self.pattern {
+            Some((_, rate)) => rate / 128.0 / self.sample_rate,
+            None => self.settings.frequency / self.sample_rate,
+        };
+        let audible = self.playing && !self.settings.muted;
+
+        for sample in out.iter_mut() {
+            let beep = match self.pattern {
+                _ if !audible => 0.0,
+                Some((pattern, _)) => {
+                    let bit = (self.phase * 128.0) as usize % 128;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                None => self.settings.waveform.sample(self.phase),
+            };
+            let digitized = self.digitized.pop_front().unwrap_or(0.0);
+            *sample = if self.settings.muted {
+                0.0
+            } else {
+                (beep + digitized).clamp(-1.0, 1.0) * self.settings.volume
+            };
+            self.phase = (self.phase + step) % 1.0;
+        }
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.fill(out);
+    }
+}
+
+/// Plays the tone through the default SDL audio device.
+#[cfg(feature = "sdl")]
+pub struct Audio {
+    device: AudioDevice<Tone>,
+}
+
+#[cfg(feature = "sdl")]
+impl Audio {
+    pub fn new(sdl_context: &sdl2::Sdl, settings: AudioSettings) -> Result<Self, Error> {
+        let audio_subsystem = sdl_context.audio().map_err(Error::Sdl)?;
+
+        let desired_spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem
+            .open_playback(None, &desired_spec, |spec| Tone::new(settings, spec.freq))
+            .map_err(Error::Sdl)?;
+        device.resume();
+
+        Ok(Audio { device })
+    }
+
+    /// Starts or stops the beep; called once per frame with whether the
+    /// sound timer is running.
+    pub fn set_playing(&mut self, playing: bool) {
+        self.device.lock().playing = playing;
+    }
+
+    /// Sets the XO-CHIP audio pattern from `Processor::get_audio_pattern`
+    /// and its rate.
+    pub fn set_pattern(&mut self, pattern: Option<&[u8; 16]>, rate: f32) {
+        self.device.lock().set_pattern(pattern, rate);
+    }
+
+    /// Queues a frame of digitized sound from
+    /// `Processor::get_digitized_sound`.
+    pub fn queue_sound(&mut self, samples: &[u8], rate: u32) {
+        self.device.lock().queue(samples, rate);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        let mut tone = self.device.lock();
+        tone.settings.muted = !tone.settings.muted;
+    }
+}
+
+/// Renders the tone into memory instead of a device, one 60Hz frame at a
+/// time, for headless runs.
+pub struct WavRecorder {
+    tone: Tone,
+    samples: Vec<f32>,
+}
+
+impl WavRecorder {
+    pub fn new(settings: AudioSettings) -> Self {
+        WavRecorder {
+            tone: Tone::new(settings, SAMPLE_RATE),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Sets the XO-CHIP audio pattern for the frames that follow, as
+    /// `Audio::set_pattern` does.
+    pub fn set_pattern(&mut self, pattern: Option<&[u8; 16]>, rate: f32) {
+        self.tone.set_pattern(pattern, rate);
+    }
+
+    /// Appends one frame of audio, the beep audible if `playing` is set,
+    /// along with the frame's digitized sound.
+    pub fn push_frame(&mut self, playing: bool, digitized: Option<(&[u8], u32)>) {
+        let start = self.samples.len();
+        self.samples
+            .resize(start + (SAMPLE_RATE as u32 / FRAME_RATE) as usize, 0.0);
+        self.tone.playing = playing;
+        if let Some((samples, rate)) = digitized {
+            self.tone.queue(samples, rate);
+        }
+        self.tone.fill(&mut self.samples[start..]);
+    }
+
+    /// Encodes everything recorded so far as a 16-bit mono PCM WAV file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_len = (self.samples.len() * 2) as u32;
+        let mut wav = Vec::with_capacity(44 + data_len as usize);
+
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&(SAMPLE_RATE as u32).to_le_bytes());
+        wav.extend_from_slice(&(SAMPLE_RATE as u32 * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for &sample in &self.samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            wav.extend_from_slice(&pcm.to_le_bytes());
+        }
+
+        wav
+    }
+}
@@ -0,0 +1,394 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use chip8_core::disasm;
+use chip8_core::opcodes::Syntax;
+use chip8_core::{InstructionSet, Processor};
+
+const HELP: &str = "debugger commands:
+    c, continue         resume execution
+    p, pause            pause execution
+    s, step             execute one instruction
+    n, next             execute one instruction, stepping over calls
+    f, finish           run until the current subroutine returns
+    b <addr>            break when PC reaches addr
+    bo <pattern>        break before an opcode matching pattern, e.g. D??0
+    bw <start> [end]    break after a write to memory in start..=end
+    d <n>               delete breakpoint n
+    l, list             list breakpoints
+    r, regs             show registers and disassembly
+    m <addr> [len]      dump memory
+    h, help             show this help
+addresses and patterns are hexadecimal";
+
+// instructions listed either side of PC
+const CONTEXT: u16 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    Address(u16),
+    /// opcodes where `opcode & mask == value`
+    Opcode {
+        value: u16,
+        mask: u16,
+    },
+    /// writes touching the inclusive address range
+    Write {
+        start: usize,
+        end: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Paused,
+    Running,
+    Step,
+    /// running until the call at the given stack depth returns to `pc`
+    StepOver {
+        sp: u16,
+        pc: u16,
+    },
+    /// running until the stack unwinds below the given depth
+    Finish {
+        sp: u16,
+    },
+}
+
+/// Pauses, steps and breaks a `Processor` from commands typed on stdin.
+pub struct Debugger {
+    commands: Receiver<String>,
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    // PC execution resumed from, so a breakpoint there doesn't fire again
+    resume_pc: Option<u16>,
+    // instructions run so far in the current frame
+    executed: usize,
+}
+
+impl Debugger {
+    /// Starts reading commands from stdin, with execution paused.
+    pub fn new() -> Self {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        println!("{}", HELP);
+        Debugger {
+            commands,
+            breakpoints: Vec::new(),
+            mode: Mode::Paused,
+            resume_pc: None,
+            executed: 0,
+        }
+    }
+
+    /// Runs one 60Hz frame under debugger control in place of
+    /// `Processor::run_frame`, handling any commands typed since the last.
+    /// A frame cut short by a pause carries on where it stopped once
+    /// execution resumes, so frames hold the same instructions as they
+    /// would without the debugger. Returns whether a frame was finished,
+    /// to be recorded.
+    pub fn run_frame<T: InstructionSet>(&mut self, chippy: &mut Processor<T>) -> bool {
+        self.handle_commands(chippy);
+
+        match self.mode {
+            Mode::Paused => return false,
+            Mode::Step => {
+                if chippy.ready() {
+                    match chippy.tick() {
+                        Ok(()) => self.executed += 1,
+                        Err(err) => println!("{}", err),
+                    }
+                }
+                let finished = !chippy.ready() || self.executed >= chippy.get_ipf();
+                if finished {
+                    self.end_frame(chippy);
+                }
+                self.pause(chippy);
+                return finished;
+            }
+            _ => {}
+        }
+
+        while self.executed < chippy.get_ipf() {
+            if !chippy.ready() {
+                break;
+            }
+
+            let pc = chippy.get_pc();
+            if self.resume_pc.take() != Some(pc) {
+                if let Some(index) = self.breakpoint_before(chippy) {
+                    println!("breakpoint {} hit", index);
+                    self.pause(chippy);
+                    return false;
+                }
+            }
+
+            // stop at a faulting instruction instead of giving up, so the
+            // state leading up to it can be inspected
+            if let Err(err) = chippy.tick() {
+                println!("{}", err);
+                self.pause(chippy);
+                return false;
+            }
+            self.executed += 1;
+
+            if let Some(index) = self.breakpoint_after(chippy) {
+                println!("breakpoint {} hit", index);
+                self.pause(chippy);
+                return false;
+            }
+
+            let done = match self.mode {
+                Mode::StepOver { sp, pc } => chippy.get_sp() == sp && chippy.get_pc() == pc,
+                Mode::Finish { sp } => chippy.get_sp() < sp,
+                _ => false,
+            };
+            if done {
+                self.pause(chippy);
+                return false;
+            }
+        }
+
+        self.end_frame(chippy);
+        true
+    }
+
+    fn end_frame<T: InstructionSet>(&mut self, chippy: &mut Processor<T>) {
+        chippy.end_frame();
+        self.executed = 0;
+    }
+
+    fn pause<T: InstructionSet>(&mut self, chippy: &Processor<T>) {
+        self.mode = Mode::Paused;
+        show_state(chippy);
+    }
+
+    fn resume<T: InstructionSet>(&mut self, chippy: &Processor<T>, mode: Mode) {
+        self.mode = mode;
+        self.resume_pc = Some(chippy.get_pc());
+    }
+
+    /// Index of a PC or opcode breakpoint matching the next instruction.
+    fn breakpoint_before<T: InstructionSet>(&self, chippy: &Processor<T>) -> Option<usize> {
+        let pc = chippy.get_pc();
+        let opcode = chippy.get_opcode_at(pc);
+        self.breakpoints.iter().position(|bp| match *bp {
+            Breakpoint::Address(addr) => addr == pc,
+            Breakpoint::Opcode { value, mask } => opcode & mask == value,
+            Breakpoint::Write { .. } => false,
+        })
+    }
+
+    /// Index of a write breakpoint touched by the last instruction.
+    fn breakpoint_after<T: InstructionSet>(&self, chippy: &Processor<T>) -> Option<usize> {
+        let (written_start, written_end) = chippy.get_last_write()?;
+        self.breakpoints.iter().position(|bp| match *bp {
+            Breakpoint::Write { start, end } => written_start <= end && start < written_end,
+            _ => false,
+        })
+    }
+
+    fn handle_commands<T: InstructionSet>(&mut self, chippy: &Processor<T>) {
+        loop {
+            let line = match self.commands.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    // stdin closed; let the program run on its own
+                    if self.mode == Mode::Paused {
+                        self.resume(chippy, Mode::Running);
+                    }
+                    return;
+                }
+            };
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["c" | "continue"] => self.resume(chippy, Mode::Running),
+                ["p" | "pause"] => self.pause(chippy),
+                ["s" | "step"] => self.resume(chippy, Mode::Step),
+                ["n" | "next"] => {
+                    let pc = chippy.get_pc();
+                    if chippy.get_opcode_at(pc) & 0xF000 == 0x2000 {
+                        let sp = chippy.get_sp();
+                        self.resume(
+                            chippy,
+                            Mode::StepOver {
+                                sp,
+                                pc: pc.wrapping_add(2),
+                            },
+                        );
+                    } else {
+                        self.resume(chippy, Mode::Step);
+                    }
+                }
+                ["f" | "finish"] => match chippy.get_sp() {
+                    0 => println!("not inside a subroutine"),
+                    sp => self.resume(chippy, Mode::Finish { sp }),
+                },
+                ["b", addr] => match parse_hex(addr) {
+                    Some(addr) => self.add_breakpoint(Breakpoint::Address(addr as u16)),
+                    None => println!("bad address '{}'", addr),
+                },
+                ["bo", pattern] => match parse_pattern(pattern) {
+                    Some((value, mask)) => self.add_breakpoint(Breakpoint::Opcode { value, mask }),
+                    None => println!("bad opcode pattern '{}'", pattern),
+                },
+                ["bw", start] | ["bw", start, _] => {
+                    let end = words.get(2).unwrap_or(start);
+                    match (parse_hex(start), parse_hex(end)) {
+                        (Some(start), Some(end)) if start <= end => {
+                            self.add_breakpoint(Breakpoint::Write { start, end })
+                        }
+                        _ => println!("bad address range"),
+                    }
+                }
+                ["d", index] => match index.parse::<usize>() {
+                    Ok(index) if index < self.breakpoints.len() => {
+                        self.breakpoints.remove(index);
+                    }
+                    _ => println!("no breakpoint '{}'", index),
+                },
+                ["l" | "list"] => {
+                    for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+                        println!("{}: {}", index, describe(breakpoint));
+                    }
+                }
+                ["r" | "regs"] => show_state(chippy),
+                ["m", addr] | ["m", addr, _] => {
+                    let len = words.get(2).and_then(|len| parse_hex(len)).unwrap_or(0x40);
+                    match parse_hex(addr) {
+                        Some(addr) => dump_memory(chippy.get_ram(), addr, len),
+                        None => println!("bad address '{}'", addr),
+                    }
+                }
+                ["h" | "help"] => println!("{}", HELP),
+                _ => println!("unknown command '{}', try 'help'", line.trim()),
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        println!("{}: {}", self.breakpoints.len(), describe(&breakpoint));
+        self.breakpoints.push(breakpoint);
+    }
+}
+
+fn describe(breakpoint: &Breakpoint) -> String {
+    match *breakpoint {
+        Breakpoint::Address(addr) => format!("pc == 0x{:03X}", addr),
+        Breakpoint::Opcode { value, mask } => {
+            let pattern: String = (0..4)
+                .rev()
+                .map(|nibble| {
+                    let shift = nibble * 4;
+                    if (mask >> shift) & 0xF == 0 {
+                        '?'
+                    } else {
+                        std::char::from_digit(((value >> shift) & 0xF) as u32, 16)
+                            .unwrap()
+                            .to_ascii_uppercase()
+                    }
+                })
+                .collect();
+            format!("opcode {}", pattern)
+        }
+        Breakpoint::Write { start, end } => format!("write to 0x{:03X}..=0x{:03X}", start, end),
+    }
+}
+
+fn show_state<T: InstructionSet>(chippy: &Processor<T>) {
+    let (delay_timer, sound_timer) = chippy.get_timers();
+    println!(
+        "PC 0x{:03X}  I 0x{:03X}  SP {}  DT {}  ST {}",
+        chippy.get_pc(),
+        chippy.get_index(),
+        chippy.get_sp(),
+        delay_timer,
+        sound_timer
+    );
+
+    for row in chippy.get_registers().chunks(8).enumerate() {
+        let (offset, values) = row;
+        let line: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X} {:02X}", offset * 8 + i, value))
+            .collect();
+        println!("{}", line.join("  "));
+    }
+
+    let stack: Vec<String> = chippy
+        .get_stack()
+        .iter()
+        .map(|addr| format!("0x{:03X}", addr))
+        .collect();
+    println!("stack [{}]", stack.join(", "));
+
+    let pc = chippy.get_pc();
+    let first = pc.saturating_sub(CONTEXT * 2);
+    for addr in (first..=pc.saturating_add(CONTEXT * 2)).step_by(2) {
+        let opcode = chippy.get_opcode_at(addr);
+        let marker = if addr == pc { '>' } else { ' ' };
+        println!(
+            "{} 0x{:03X}  {:04X}  {}",
+            marker,
+            addr,
+            opcode,
+            disasm::instruction(
+                opcode,
+                chippy.get_opcode_at(addr.wrapping_add(2)),
+                Syntax::Classic
+            )
+        );
+    }
+
+    print!("(debug) ");
+    let _ = io::stdout().flush();
+}
+
+fn dump_memory(ram: &[u8], addr: usize, len: usize) {
+    let end = addr.saturating_add(len).min(ram.len());
+    for line_start in (addr..end).step_by(16) {
+        let bytes: Vec<String> = ram[line_start..(line_start + 16).min(end)]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        println!("0x{:03X}  {}", line_start, bytes.join(" "));
+    }
+}
+
+/// Parses a hexadecimal number, with or without a `0x` prefix.
+fn parse_hex(text: &str) -> Option<usize> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16).ok()
+}
+
+/// Parses a four-digit opcode pattern where `?` matches any nibble,
+/// returning the value and mask to compare opcodes against.
+fn parse_pattern(pattern: &str) -> Option<(u16, u16)> {
+    if pattern.len() != 4 {
+        return None;
+    }
+
+    let mut value = 0;
+    let mut mask = 0;
+    for c in pattern.chars() {
+        value <<= 4;
+        mask <<= 4;
+        if c != '?' {
+            value |= c.to_digit(16)? as u16;
+            mask |= 0xF;
+        }
+    }
+    Some((value, mask))
+}
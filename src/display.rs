@@ -1,72 +1,167 @@
-use sdl2::pixels;
+use sdl2::pixels::{self, PixelFormatEnum};
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 
-const SCREEN_WIDTH: usize = 64;
-const SCREEN_HEIGHT: usize = 32;
-const SCALE_FACTOR: u32 = 12;
+use chip8_core::processor::{ColorLayer, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip8_core::Error;
+
+const WINDOW_WIDTH: u32 = 768;
+const WINDOW_HEIGHT: u32 = 384;
+
+// colours for each combination of lit drawing planes
+const PALETTE: [pixels::Color; 4] = [
+    pixels::Color::RGB(0, 0, 0),
+    pixels::Color::RGB(255, 255, 255),
+    pixels::Color::RGB(170, 170, 170),
+    pixels::Color::RGB(85, 85, 85),
+];
 
 pub struct Display {
     canvas: Canvas<Window>,
+    // PALETTE with any colours the ROM database gives in its place
+    palette: [pixels::Color; 4],
+    // resolution of the last frame drawn
+    resolution: (usize, usize),
 }
 
 impl Display {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        let video_subsystem = match sdl_context.video() {
-            Ok(video) => video,
-            Err(err) => panic!(
-                "Could not obtain handle to the video subsystem! SDL_Error: {}",
-                err
-            ),
-        };
+    /// Opens the window, titled after the ROM if its title is known.
+    /// `colors` are RGB values replacing the start of the palette.
+    pub fn new(
+        sdl_context: &sdl2::Sdl,
+        title: Option<&str>,
+        colors: &[u32],
+    ) -> Result<Self, Error> {
+        let video_subsystem = sdl_context.video().map_err(Error::Sdl)?;
 
         let window = video_subsystem
             .window(
-                "rust-sdl2 Chip-8",
-                SCREEN_WIDTH as u32 * SCALE_FACTOR,
-                SCREEN_HEIGHT as u32 * SCALE_FACTOR,
+                title.unwrap_or("rust-sdl2 Chip-8"),
+                WINDOW_WIDTH,
+                WINDOW_HEIGHT,
             )
-            //.window("rust-sdl2 demo: Video", SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
             .position_centered()
             .opengl()
             .build()
-            .unwrap();
+            .map_err(|e| Error::Sdl(e.to_string()))?;
 
         let mut canvas = window
             .into_canvas()
             .build()
-            .map_err(|e| e.to_string())
-            .unwrap();
+            .map_err(|e| Error::Sdl(e.to_string()))?;
 
         canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
         canvas.clear();
         canvas.present();
 
-        Display { canvas: canvas }
+        let mut palette = PALETTE;
+        for (color, &rgb) in palette.iter_mut().zip(colors) {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            *color = pixels::Color::RGB(r, g, b);
+        }
+
+        Ok(Display {
+            canvas,
+            palette,
+            resolution: (SCREEN_WIDTH, SCREEN_HEIGHT),
+        })
     }
 
-    pub fn draw(&mut self, screen: &[[bool; SCREEN_WIDTH]; SCREEN_HEIGHT]) {
-        self.canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
-        self.canvas.clear();
+    /// Paints the top-left `width` x `height` pixels of `screen`, scaled to
+    /// fill the window. With a colour layer, pixels take its colours
+    /// instead of the grey palette.
+    pub fn draw(
+        &mut self,
+        screen: &[[u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+        (width, height): (usize, usize),
+        colors: Option<&ColorLayer>,
+    ) {
+        let scale_factor = self.fit((width, height));
 
-        self.canvas
-            .set_draw_color(pixels::Color::RGB(255, 255, 255));
+        let background = match colors {
+            Some(colors) => rgb(colors.background),
+            None => self.palette[0],
+        };
+        self.canvas.set_draw_color(background);
+        self.canvas.clear();
 
-        for (y, row) in screen.iter().enumerate() {
-            for (x, &pixel) in row.iter().enumerate() {
-                if !pixel {
+        for (y, row) in screen.iter().take(height).enumerate() {
+            for (x, &pixel) in row.iter().take(width).enumerate() {
+                if pixel == 0 {
                     continue;
                 }
 
+                let color = match colors {
+                    Some(colors) => rgb(colors.color_at(x, y, true)),
+                    None => self.palette[pixel as usize & 0x03],
+                };
+                self.canvas.set_draw_color(color);
+
                 let _ = self.canvas.fill_rect(Rect::new(
-                    (x as u32 * SCALE_FACTOR) as i32,
-                    (y as u32 * SCALE_FACTOR) as i32,
-                    SCALE_FACTOR,
-                    SCALE_FACTOR,
+                    (x as u32 * scale_factor) as i32,
+                    (y as u32 * scale_factor) as i32,
+                    scale_factor,
+                    scale_factor,
                 ));
             }
         }
         self.canvas.present();
     }
+
+    /// Paints MegaChip's ARGB framebuffer of the given size, scaled to
+    /// fill the window.
+    pub fn draw_framebuffer(&mut self, frame: &[u32], (width, height): (usize, usize)) {
+        let scale_factor = self.fit((width, height));
+        let target = Rect::new(
+            0,
+            0,
+            width as u32 * scale_factor,
+            height as u32 * scale_factor,
+        );
+        let pixels: Vec<u8> = frame.iter().flat_map(|argb| argb.to_ne_bytes()).collect();
+
+        let texture_creator = self.canvas.texture_creator();
+        let result = texture_creator
+            .create_texture_static(PixelFormatEnum::ARGB8888, width as u32, height as u32)
+            .map_err(|err| err.to_string())
+            .and_then(|mut texture| {
+                texture
+                    .update(None, &pixels, width * 4)
+                    .map_err(|err| err.to_string())?;
+                self.canvas.set_draw_color(self.palette[0]);
+                self.canvas.clear();
+                self.canvas.copy(&texture, None, target)
+            });
+        if let Err(err) = result {
+            eprintln!("could not draw frame: {}", err);
+        }
+        self.canvas.present();
+    }
+
+    /// Reshapes the window when the display changes to a different aspect
+    /// ratio, such as HIRES CHIP-8's square one, and returns how many
+    /// window pixels wide a display pixel is drawn.
+    fn fit(&mut self, (width, height): (usize, usize)) -> u32 {
+        let (old_width, old_height) = self.resolution;
+        if width * old_height != height * old_width {
+            let window_height = WINDOW_WIDTH * height as u32 / width as u32;
+            if let Err(err) = self
+                .canvas
+                .window_mut()
+                .set_size(WINDOW_WIDTH, window_height)
+            {
+                eprintln!("could not resize window: {}", err);
+            }
+        }
+        self.resolution = (width, height);
+
+        let (window_width, window_height) = self.canvas.window().size();
+        (window_width / width as u32).min(window_height / height as u32)
+    }
+}
+
+fn rgb(color: u8) -> pixels::Color {
+    let (r, g, b) = ColorLayer::rgb(color);
+    pixels::Color::RGB(r, g, b)
 }
@@ -0,0 +1,210 @@
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chip8_core::movie::Movie;
+use chip8_core::rewind::Rewind;
+use chip8_core::savestate;
+use chip8_core::{Error, InstructionSet, Processor};
+
+use crate::audio::{Audio, WavRecorder};
+use crate::debugger::Debugger;
+use crate::display::Display;
+use crate::input::{Hotkey, Input};
+use crate::keymap::Keymap;
+use crate::Options;
+
+/// Plays a loaded ROM in an SDL window until it exits or the window is
+/// closed, taking keys from the movie being played back while it lasts.
+pub fn run<T: InstructionSet>(mut chippy: Processor<T>, options: &Options) -> Result<(), Error> {
+    let sdl_context = sdl2::init().map_err(Error::Sdl)?;
+
+    let mut display = Display::new(&sdl_context, options.title.as_deref(), &options.colors)?;
+    let keymap = Keymap::load(
+        options.keymap_path.as_deref(),
+        &options.filepath,
+        chippy.rom_hash(),
+        &options.rom_keys,
+    )?;
+    let mut input = Input::new(&sdl_context, keymap)?;
+    let mut audio = match options.wav_path {
+        Some(_) => None,
+        None => Some(Audio::new(&sdl_context, options.audio)?),
+    };
+    let mut recorder = options
+        .wav_path
+        .as_ref()
+        .map(|_| WavRecorder::new(options.audio));
+
+    // appending carries on the movie being played back; otherwise a new
+    // recording starts from wherever the machine is now
+    let mut recording = match &options.record_path {
+        Some(_) if options.append => options.movie.clone(),
+        Some(_) => {
+            let from_state = options.state_path.is_some()
+                || options
+                    .movie
+                    .as_ref()
+                    .is_some_and(|movie| movie.start_state.is_some());
+            Some(Movie::new(&chippy, &options.rng, options.seed, from_state))
+        }
+        None => None,
+    };
+    let mut frame = 0;
+
+    let mut rewind = Rewind::new(options.rewind_seconds * 60, chippy.get_ram().len());
+    // frames the rewind key has been held for
+    let mut rewinding: usize = 0;
+    let mut debugger = if options.debug {
+        Some(Debugger::new())
+    } else {
+        None
+    };
+
+    let frame_duration = Duration::from_micros(1_000_000 / 60);
+    let mut next_frame = Instant::now();
+
+    let mut result = Ok(());
+    while !chippy.halted() {
+        let (keypad, keypad2) = match input.poll() {
+            Ok(keypads) => keypads,
+            Err(()) => break,
+        };
+        let movie = options.movie.as_ref();
+        let keypad = movie
+            .and_then(|movie| movie.keypad(frame))
+            .unwrap_or(keypad);
+        let keypad2 = movie
+            .and_then(|movie| movie.keypad2(frame))
+            .unwrap_or(keypad2);
+
+        for hotkey in input.take_hotkeys() {
+            match hotkey {
+                Hotkey::ToggleMute => {
+                    if let Some(audio) = audio.as_mut() {
+                        audio.toggle_mute();
+                    }
+                }
+                Hotkey::SaveState(slot) => {
+                    let path = savestate::slot_path(&options.filepath, slot);
+                    match savestate::write_file(&path, chippy.rom_hash(), &chippy.save_state()) {
+                        Ok(()) => println!("saved state to slot {}", slot),
+                        Err(err) => eprintln!("{}", err),
+                    }
+                }
+                Hotkey::LoadState(_) if recording.is_some() => {
+                    eprintln!("can't load a state while recording a movie");
+                }
+                Hotkey::LoadState(slot) => {
+                    let path = savestate::slot_path(&options.filepath, slot);
+                    match savestate::read_file(&path, chippy.rom_hash())
+                        .and_then(|state| chippy.load_state(&state))
+                    {
+                        Ok(()) => println!("loaded state from slot {}", slot),
+                        Err(err) => eprintln!("{}", err),
+                    }
+                }
+            }
+        }
+
+        let mut playing = false;
+        let mut digitized = None;
+        if input.rewind_held() {
+            // step back through the snapshots as fast as they were taken
+            // instead of running
+            let snapshot = if rewinding.is_multiple_of(rewind.interval()) {
+                rewind.pop()
+            } else {
+                None
+            };
+            rewinding += 1;
+            if let Some((snapshot_frame, state)) = snapshot {
+                match chippy.load_state(&state) {
+                    Ok(()) => {
+                        // undone frames are recorded over
+                        frame = snapshot_frame;
+                        if let Some(recording) = recording.as_mut() {
+                            recording.truncate(frame);
+                        }
+                    }
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+        } else {
+            rewinding = 0;
+            chippy.set_keypad(&keypad);
+            chippy.set_keypad2(&keypad2);
+            let finished = match debugger.as_mut() {
+                Some(debugger) => debugger.run_frame(&mut chippy),
+                None => {
+                    if let Err(err) = chippy.run_frame() {
+                        result = Err(err);
+                        break;
+                    }
+                    true
+                }
+            };
+            crate::report_ignored(&mut chippy);
+            if finished {
+                if let Some(recording) = recording.as_mut() {
+                    // frames an appended-to movie already has are only replayed
+                    if recording.len() == frame {
+                        recording.push(&keypad, &keypad2);
+                    }
+                }
+                frame += 1;
+                if rewind.due(frame) {
+                    rewind.push(frame, chippy.save_state());
+                }
+                playing = chippy.sound_active();
+                digitized = chippy.get_digitized_sound();
+            }
+        }
+
+        let pattern = chippy.get_audio_pattern();
+        let pattern_rate = chippy.get_pattern_rate();
+        if let Some(audio) = audio.as_mut() {
+            audio.set_pattern(pattern, pattern_rate);
+            audio.set_playing(playing);
+            if let Some((samples, rate)) = digitized {
+                audio.queue_sound(samples, rate);
+            }
+        }
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.set_pattern(pattern, pattern_rate);
+            recorder.push_frame(playing, digitized);
+        }
+
+        if chippy.display_stale() {
+            match chippy.get_framebuffer() {
+                Some(frame) => display.draw_framebuffer(frame, chippy.get_resolution()),
+                None => display.draw(
+                    chippy.get_screen(),
+                    chippy.get_resolution(),
+                    chippy.get_colors(),
+                ),
+            }
+        }
+
+        // hold the frame rate at 60Hz, catching up after a slow frame
+        next_frame += frame_duration;
+        match next_frame.checked_duration_since(Instant::now()) {
+            Some(remaining) => thread::sleep(remaining),
+            None => next_frame = Instant::now(),
+        }
+    }
+
+    if let (Some(path), Some(recorder)) = (&options.wav_path, &recorder) {
+        if let Err(err) = fs::write(path, recorder.to_wav()) {
+            eprintln!("could not write {}: {}", path, err);
+        }
+    }
+    if let (Some(path), Some(recording)) = (&options.record_path, &recording) {
+        match recording.write_file(path) {
+            Ok(()) => println!("recorded {} frames to {}", recording.len(), path),
+            Err(err) => eprintln!("could not write {}: {}", path, err),
+        }
+    }
+
+    result
+}
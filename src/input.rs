@@ -1,63 +1,168 @@
-use sdl2;
+use sdl2::controller::GameController;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::GameControllerSubsystem;
+
+use chip8_core::Error;
+
+use crate::keymap::{Binding, Keymap};
+
+// how far a stick or trigger has to move to count as a press
+const AXIS_THRESHOLD: i16 = 16384;
+
+/// Keys kept for the emulator, which a keymap can't bind: Escape quits, M
+/// mutes, F1-F4 load states and save them with shift, and holding
+/// Backspace rewinds.
+pub const RESERVED_KEYS: [Keycode; 7] = [
+    Keycode::Escape,
+    Keycode::M,
+    Keycode::F1,
+    Keycode::F2,
+    Keycode::F3,
+    Keycode::F4,
+    Keycode::Backspace,
+];
+
+/// Emulator controls, separate from the CHIP-8 keypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    ToggleMute,
+    SaveState(u8),
+    LoadState(u8),
+}
 
 pub struct Input {
     event_pump: sdl2::EventPump,
+    controller_subsystem: GameControllerSubsystem,
+    // every controller plugged in, opened as SDL reports them
+    controllers: Vec<GameController>,
+    keymap: Keymap,
+    hotkeys: Vec<Hotkey>,
+    rewind_held: bool,
 }
 
 impl Input {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
-        Input {
-            event_pump: sdl_context.event_pump().unwrap(),
-        }
+    pub fn new(sdl_context: &sdl2::Sdl, keymap: Keymap) -> Result<Self, Error> {
+        Ok(Input {
+            event_pump: sdl_context.event_pump().map_err(Error::Sdl)?,
+            controller_subsystem: sdl_context.game_controller().map_err(Error::Sdl)?,
+            controllers: Vec::new(),
+            keymap,
+            hotkeys: Vec::new(),
+            rewind_held: false,
+        })
     }
 
-    pub fn poll(&mut self) -> Result<[bool; 16], ()> {
-        let mut keypad = [false; 16];
+    /// Reads the keys held on both keypads for the coming frame. A key
+    /// pressed since the last poll counts as held even if it has already
+    /// been let go, so a quick tap still reaches the program as a press and
+    /// a release.
+    pub fn poll(&mut self) -> Result<([bool; 16], [bool; 16]), ()> {
+        let mut keys = [false; 32];
+        let mut tapped_keys = Vec::new();
+        let mut tapped_buttons = Vec::new();
 
         for event in self.event_pump.poll_iter() {
-            if let Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } = event
-            {
-                return Err(());
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Err(()),
+                // also sent at startup for controllers already plugged in
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match self.controller_subsystem.open(which) {
+                        Ok(controller) => {
+                            println!("connected {}", controller.name());
+                            self.controllers.push(controller);
+                        }
+                        Err(err) => eprintln!("could not open controller {}: {}", which, err),
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers
+                        .retain(|controller| controller.instance_id() != which);
+                }
+                Event::ControllerButtonDown { button, .. } => tapped_buttons.push(button),
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => self.hotkeys.push(Hotkey::ToggleMute),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    tapped_keys.push(keycode);
+
+                    // F1-F4 load from save slots 1-4, shift+F1-F4 save to them
+                    let slot = match keycode {
+                        Keycode::F1 => 1,
+                        Keycode::F2 => 2,
+                        Keycode::F3 => 3,
+                        Keycode::F4 => 4,
+                        _ => continue,
+                    };
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        self.hotkeys.push(Hotkey::SaveState(slot));
+                    } else {
+                        self.hotkeys.push(Hotkey::LoadState(slot));
+                    }
+                }
+                _ => {}
             }
         }
 
-        for keycode in self
+        let mut keycodes: Vec<Keycode> = self
             .event_pump
             .keyboard_state()
             .pressed_scancodes()
             .filter_map(Keycode::from_scancode)
-        {
-            let idx = match keycode {
-                Keycode::Num1 => Some(0x1),
-                Keycode::Num2 => Some(0x2),
-                Keycode::Num3 => Some(0x3),
-                Keycode::Num4 => Some(0xC),
-                Keycode::Q => Some(0x4),
-                Keycode::W => Some(0x5),
-                Keycode::E => Some(0x6),
-                Keycode::R => Some(0xD),
-                Keycode::A => Some(0x7),
-                Keycode::S => Some(0x8),
-                Keycode::D => Some(0x9),
-                Keycode::F => Some(0xE),
-                Keycode::Z => Some(0xA),
-                Keycode::X => Some(0x0),
-                Keycode::C => Some(0xB),
-                Keycode::V => Some(0xF),
-                _ => None,
-            };
-
-            if let Some(i) = idx {
-                keypad[i] = true;
-            }
+            .collect();
+        keycodes.extend(tapped_keys);
+        self.rewind_held = keycodes.contains(&Keycode::Backspace);
+
+        for (key, pressed) in keys.iter_mut().enumerate() {
+            *pressed = self
+                .keymap
+                .bindings(key)
+                .iter()
+                .any(|&binding| match binding {
+                    Binding::Key(keycode) => keycodes.contains(&keycode),
+                    Binding::Button(button) => {
+                        tapped_buttons.contains(&button)
+                            || self
+                                .controllers
+                                .iter()
+                                .any(|controller| controller.button(button))
+                    }
+                    Binding::Axis(axis, positive) => self.controllers.iter().any(|controller| {
+                        let value = controller.axis(axis);
+                        if positive {
+                            value > AXIS_THRESHOLD
+                        } else {
+                            value < -AXIS_THRESHOLD
+                        }
+                    }),
+                });
         }
 
-        return Ok(keypad);
+        let mut keypad = [false; 16];
+        let mut keypad2 = [false; 16];
+        keypad.copy_from_slice(&keys[..16]);
+        keypad2.copy_from_slice(&keys[16..]);
+        Ok((keypad, keypad2))
+    }
+
+    /// Whether the rewind key was held down at the last poll.
+    pub fn rewind_held(&self) -> bool {
+        self.rewind_held
+    }
+
+    /// Hotkeys pressed since the last call.
+    pub fn take_hotkeys(&mut self) -> Vec<Hotkey> {
+        std::mem::take(&mut self.hotkeys)
     }
 }
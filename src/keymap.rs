@@ -0,0 +1,330 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::Keycode;
+
+use chip8_core::Error;
+
+use crate::config_path;
+use crate::input::RESERVED_KEYS;
+
+// bindings for each key, and those replacing them for the ROM being run
+type Layers = ([Vec<Binding>; 32], [Option<Vec<Binding>>; 32]);
+
+/// Something on the keyboard or a game controller that can press a CHIP-8
+/// key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Key(Keycode),
+    Button(Button),
+    /// an axis pushed past the threshold, `true` in the positive direction
+    Axis(Axis, bool),
+}
+
+impl Binding {
+    fn parse(text: &str) -> Result<Self, String> {
+        let (kind, name) = text
+            .split_once(char::is_whitespace)
+            .map(|(kind, name)| (kind, name.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "expected 'key', 'button' or 'axis' and a name in '{}'",
+                    text
+                )
+            })?;
+
+        match kind {
+            "key" => Keycode::from_name(name)
+                .map(Binding::Key)
+                .ok_or_else(|| format!("unknown key '{}'", name)),
+            "button" => Button::from_string(name)
+                .map(Binding::Button)
+                .ok_or_else(|| format!("unknown controller button '{}'", name)),
+            "axis" => {
+                let (axis, positive) = match name.strip_suffix('+') {
+                    Some(axis) => (axis, true),
+                    None => match name.strip_suffix('-') {
+                        Some(axis) => (axis, false),
+                        None => return Err(format!("axis '{}' needs a direction, + or -", name)),
+                    },
+                };
+                Axis::from_string(axis)
+                    .map(|axis| Binding::Axis(axis, positive))
+                    .ok_or_else(|| format!("unknown controller axis '{}'", axis))
+            }
+            _ => Err(format!("unknown binding type '{}'", kind)),
+        }
+    }
+}
+
+/// Which keys, buttons and axes press each of the 16 CHIP-8 keys, and of
+/// the 16 on CHIP-8X's second keypad.
+///
+/// A keymap file has one line per CHIP-8 key: the hex key, written `2:k`
+/// for the second keypad, `=`, then a comma-separated list of bindings.
+/// Lines under a `[name]` header only apply to the ROM with that file name
+/// or SHA-1 hash, and replace the bindings given for the same key above
+/// any header. `#` starts a comment.
+///
+/// ```text
+/// 5 = key W, button dpup, axis lefty-
+/// 7 = key A, button dpleft, axis leftx-
+///
+/// [pong.ch8]
+/// 1 = key Up
+/// 4 = key Down
+/// 2:c = key Keypad 8
+/// ```
+///
+/// Key names are SDL's (`Up`, `Space`, `Keypad 5`); button and axis names
+/// are SDL's game controller ones (`a`, `dpup`, `leftx`, `lefttrigger`).
+/// The hotkeys, Escape, M, F1 to F4 and Backspace, can't be bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    // the second keypad's keys follow the first's
+    bindings: [Vec<Binding>; 32],
+}
+
+impl Default for Keymap {
+    /// The usual layout of the left of a QWERTY keyboard, with the d-pad
+    /// and left stick on the 5/7/8/9 cross most games steer with.
+    fn default() -> Self {
+        let keys = [
+            (0x1, Keycode::Num1),
+            (0x2, Keycode::Num2),
+            (0x3, Keycode::Num3),
+            (0xC, Keycode::Num4),
+            (0x4, Keycode::Q),
+            (0x5, Keycode::W),
+            (0x6, Keycode::E),
+            (0xD, Keycode::R),
+            (0x7, Keycode::A),
+            (0x8, Keycode::S),
+            (0x9, Keycode::D),
+            (0xE, Keycode::F),
+            (0xA, Keycode::Z),
+            (0x0, Keycode::X),
+            (0xB, Keycode::C),
+            (0xF, Keycode::V),
+        ];
+        let pad = [
+            (0x5, Binding::Button(Button::DPadUp)),
+            (0x5, Binding::Axis(Axis::LeftY, false)),
+            (0x8, Binding::Button(Button::DPadDown)),
+            (0x8, Binding::Axis(Axis::LeftY, true)),
+            (0x7, Binding::Button(Button::DPadLeft)),
+            (0x7, Binding::Axis(Axis::LeftX, false)),
+            (0x9, Binding::Button(Button::DPadRight)),
+            (0x9, Binding::Axis(Axis::LeftX, true)),
+            (0x6, Binding::Button(Button::A)),
+            (0x4, Binding::Button(Button::B)),
+        ];
+
+        let mut keymap = Keymap {
+            bindings: Default::default(),
+        };
+        for (key, keycode) in keys {
+            keymap.bindings[key].push(Binding::Key(keycode));
+        }
+        for (key, binding) in pad {
+            keymap.bindings[key].push(binding);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Reads a keymap file, keeping the overrides for the ROM with the given
+    /// file name and hex SHA-1 hash apart from the general bindings.
+    fn parse(text: &str, rom_name: &str, rom_hash: &str) -> Result<Layers, Error> {
+        let mut bindings: [Vec<Binding>; 32] = Default::default();
+        let mut overrides: [Option<Vec<Binding>>; 32] = Default::default();
+        // None above any header, otherwise whether the header names this ROM
+        let mut section = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| Error::Keymap {
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let name = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| error(String::from("expected ']'")))?
+                    .trim();
+                section = Some(name == rom_name || name.eq_ignore_ascii_case(rom_hash));
+                continue;
+            }
+
+            let (key, list) = line
+                .split_once('=')
+                .ok_or_else(|| error(String::from("expected '<key> = <bindings>'")))?;
+            let (key, offset) = match key.trim().strip_prefix("2:") {
+                Some(key) => (key, 16),
+                None => (key.trim(), 0),
+            };
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => offset + key as usize,
+                _ => return Err(error(String::from("keys are hex digits 0 to f"))),
+            };
+            let list = list
+                .split(',')
+                .map(|text| match Binding::parse(text.trim())? {
+                    Binding::Key(keycode) if RESERVED_KEYS.contains(&keycode) => {
+                        Err(format!("'{}' is a hotkey", text.trim()))
+                    }
+                    binding => Ok(binding),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?;
+
+            match section {
+                None => bindings[key].extend(list),
+                Some(true) => overrides[key].get_or_insert_with(Vec::new).extend(list),
+                Some(false) => {}
+            }
+        }
+
+        Ok((bindings, overrides))
+    }
+
+    fn merge(mut bindings: [Vec<Binding>; 32], overrides: [Option<Vec<Binding>>; 32]) -> Self {
+        for (key, list) in overrides.into_iter().enumerate() {
+            if let Some(list) = list {
+                bindings[key] = list;
+            }
+        }
+        Keymap { bindings }
+    }
+
+    /// Loads the keymap from `path`, or from `keymap` in the user's config
+    /// directory if no path is given. Without either file the default
+    /// layout is used. `rom_keys` holds the ROM database's key lines for the
+    /// ROM, which replace the general bindings of the keys they name but
+    /// not those in the file's own section for the ROM.
+    pub fn load(
+        path: Option<&str>,
+        rom_path: &str,
+        rom_hash: &[u8],
+        rom_keys: &str,
+    ) -> Result<Self, Error> {
+        let path = match path {
+            Some(path) => Some(PathBuf::from(path)),
+            None => config_path("keymap").filter(|path| path.exists()),
+        };
+
+        let rom_name = Path::new(rom_path)
+            .file_name()
+            .map_or(rom_path.into(), |name| name.to_string_lossy());
+        let rom_hash: String = rom_hash
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let (mut bindings, overrides) = match path {
+            Some(path) => Self::parse(&fs::read_to_string(path)?, &rom_name, &rom_hash)?,
+            None => (Keymap::default().bindings, Default::default()),
+        };
+        let (suggested, _) = Self::parse(rom_keys, &rom_name, &rom_hash)?;
+        for (key, list) in suggested.into_iter().enumerate() {
+            if !list.is_empty() {
+                bindings[key] = list;
+            }
+        }
+        Ok(Self::merge(bindings, overrides))
+    }
+
+    /// What presses CHIP-8 key `key`, or key `key - 16` of the second
+    /// keypad.
+    pub fn bindings(&self, key: usize) -> &[Binding] {
+        &self.bindings[key]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn parse(text: &str) -> Result<Layers, Error> {
+        Keymap::parse(text, "pong.ch8", HASH)
+    }
+
+    fn error_line(text: &str) -> (usize, String) {
+        match parse(text) {
+            Err(Error::Keymap { line, message }) => (line, message),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn lines_bind_keys_on_both_keypads() {
+        let text = "# movement\n5 = key W, button dpup\n\n2:c = axis lefty- # second\n5 = key Up";
+        let (bindings, overrides) = parse(text).unwrap();
+        assert_eq!(
+            bindings[0x5],
+            [
+                Binding::Key(Keycode::W),
+                Binding::Button(Button::DPadUp),
+                Binding::Key(Keycode::Up)
+            ]
+        );
+        assert_eq!(bindings[16 + 0xC], [Binding::Axis(Axis::LeftY, false)]);
+        assert!(bindings[0x6].is_empty());
+        assert!(overrides.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn sections_for_the_rom_replace_the_general_bindings() {
+        let text = "1 = key Q\n2 = key W\n[pong.ch8]\n1 = key Up\n[other.ch8]\n2 = key Down\n[0123456789ABCDEF0123456789ABCDEF01234567]\n3 = key E";
+        let (bindings, overrides) = parse(text).unwrap();
+        let keymap = Keymap::merge(bindings, overrides);
+        assert_eq!(keymap.bindings(1), [Binding::Key(Keycode::Up)]);
+        assert_eq!(keymap.bindings(2), [Binding::Key(Keycode::W)]);
+        assert_eq!(keymap.bindings(3), [Binding::Key(Keycode::E)]);
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        assert_eq!(
+            error_line("1 = key Q\n[pong.ch8"),
+            (2, String::from("expected ']'"))
+        );
+        assert_eq!(
+            error_line("\n\nkey Q"),
+            (3, String::from("expected '<key> = <bindings>'"))
+        );
+        assert_eq!(
+            error_line("g = key Q"),
+            (1, String::from("keys are hex digits 0 to f"))
+        );
+        assert_eq!(
+            error_line("1 = key Nothing"),
+            (1, String::from("unknown key 'Nothing'"))
+        );
+        assert_eq!(
+            error_line("1 = axis leftx"),
+            (1, String::from("axis 'leftx' needs a direction, + or -"))
+        );
+        assert_eq!(
+            error_line("1 = pedal left"),
+            (1, String::from("unknown binding type 'pedal'"))
+        );
+    }
+
+    #[test]
+    fn hotkeys_cannot_be_bound() {
+        for key in ["Escape", "M", "F1", "F4", "Backspace"] {
+            assert_eq!(
+                error_line(&format!("1 = key Q, key {}", key)),
+                (1, format!("'key {}' is a hotkey", key))
+            );
+        }
+    }
+}
@@ -1,40 +1,546 @@
 use std::env;
-use std::thread;
-use std::time::Duration;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
 
+mod audio;
+#[cfg(feature = "sdl")]
+mod debugger;
+#[cfg(feature = "sdl")]
 mod display;
+#[cfg(feature = "sdl")]
+mod frontend;
+#[cfg(feature = "sdl")]
 mod input;
-mod processor;
+#[cfg(feature = "sdl")]
+mod keymap;
 
-use display::Display;
-use input::Input;
-use processor::Processor;
-use processor::SuperChip;
+use audio::{AudioSettings, WavRecorder, Waveform};
+use chip8_core::headless::{self, KeyScript, KeySource, RunLimits, Stop};
+use chip8_core::movie::Movie;
+use chip8_core::opcodes::Syntax;
+use chip8_core::processor::{DEFAULT_IPF, START_ADDRESS};
+use chip8_core::random;
+use chip8_core::romdb::{Rom, RomDatabase};
+use chip8_core::{asm, disasm, savestate};
+use chip8_core::{
+    dump, Chip8X, CosmacVip, Error, Eti660, HiresChip, InstructionSet, MegaChip, Processor, Quirks,
+    SuperChip, XoChip,
+};
+#[cfg(feature = "sdl")]
+use frontend::run;
+
+const USAGE: &str = "usage: chip-8 [options] <rom>
+       chip-8 disasm [--octo] [--origin <addr>] <rom>
+       chip-8 asm [--origin <addr>] [-o <rom>] <source>
+
+options:
+    --isa <name>                                instruction set: vip, hires, chip8x, eti660, schip, mega or xo
+                                                (default from the ROM database, else guessed from the ROM)
+    --romdb <file>                              ROM database of per-ROM settings, added to the built-in one (default ~/.config/chip-8/romdb.json)
+    --quirks <vip|chip48|schip10|schip11|xo>    quirks preset (default per isa)
+    --load-address <addr>                       load and start the ROM at a hexadecimal address
+    --ipf <n>                                   instructions per frame
+    --strict                                    stop on opcodes the instruction set doesn't define
+    --seed <n>                                  seed for RND, to make runs repeatable
+    --rng <splitmix|vip>                        RND algorithm (default splitmix)
+    --tone <hz>                                 buzzer frequency
+    --volume <0-1>                              buzzer volume
+    --waveform <square|sine|triangle|sawtooth>  buzzer waveform
+    --mute                                      start with sound muted
+    --wav <file>                                record sound to a WAV file instead of playing it
+    --rewind <seconds>                          length of rewind history (default 30)
+    --debug                                     start paused, taking debugger commands on stdin
+    --keymap <file>                             keyboard and controller bindings (default ~/.config/chip-8/keymap)
+    --state <file>                              start from a save state
+    --record <file>                             record the keypad to a movie file
+    --play <file>                               play back a movie, using its settings
+    --append                                    play back the --record movie, then record more onto it
+
+headless options:
+    --headless                                  run without a window, as fast as possible
+    --frames <n>                                frames to run before stopping (default 600)
+    --until-pc <addr>                           stop when PC reaches a hexadecimal address
+    --until-spin                                stop at a jump to itself
+    --keys <file>                               keypad script of '<frame> +key -key' lines
+    --screen <file>                             write the screen as text, or PNG if the name ends .png
+    --regs <file>                               write the registers as JSON";
+
+struct Options {
+    isa: String,
+    /// settings for the ROM from the ROM database
+    title: Option<String>,
+    colors: Vec<u32>,
+    rom_keys: String,
+    romdb_path: Option<String>,
+    load_address: Option<u16>,
+    quirks: Option<Quirks>,
+    ipf: usize,
+    strict: bool,
+    seed: u64,
+    rng: String,
+    audio: AudioSettings,
+    wav_path: Option<String>,
+    rewind_seconds: usize,
+    debug: bool,
+    keymap_path: Option<String>,
+    state_path: Option<String>,
+    record_path: Option<String>,
+    play_path: Option<String>,
+    append: bool,
+    /// the movie played back, whose settings replace the ones given
+    movie: Option<Movie>,
+    headless: bool,
+    limits: RunLimits,
+    keys_path: Option<String>,
+    screen_path: Option<String>,
+    regs_path: Option<String>,
+    filepath: String,
+    /// the ROM image, read once for the database lookup and loading
+    rom: Vec<u8>,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filepath = &args[1];
+    match env::args().nth(1).as_deref() {
+        Some("disasm") => return disasm_command(),
+        Some("asm") => return asm_command(),
+        _ => {}
+    }
+
+    let options = parse_args();
+
+    let result = match options.isa.as_str() {
+        "vip" => start(
+            Processor::new(CosmacVip, options.quirks.unwrap_or(Quirks::VIP)),
+            &options,
+        ),
+        "hires" => start(
+            Processor::new(HiresChip, options.quirks.unwrap_or(Quirks::VIP)),
+            &options,
+        ),
+        "chip8x" => start(
+            Processor::new(Chip8X, options.quirks.unwrap_or(Quirks::VIP)),
+            &options,
+        ),
+        "eti660" => start(
+            Processor::new(Eti660, options.quirks.unwrap_or(Quirks::VIP)),
+            &options,
+        ),
+        "schip" => start(
+            Processor::new(SuperChip, options.quirks.unwrap_or(Quirks::SCHIP_1_1)),
+            &options,
+        ),
+        "mega" => start(
+            Processor::new(MegaChip, options.quirks.unwrap_or(Quirks::SCHIP_1_1)),
+            &options,
+        ),
+        "xo" => start(
+            Processor::new(XoChip, options.quirks.unwrap_or(Quirks::XO_CHIP)),
+            &options,
+        ),
+        _ => usage_error(&format!("unknown instruction set '{}'", options.isa)),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}: {}", options.filepath, err);
+        process::exit(1);
+    }
+}
+
+/// Loads the ROM and plays it in a window, or runs it headless if asked to.
+fn start<T: InstructionSet>(mut chippy: Processor<T>, options: &Options) -> Result<(), Error> {
+    let rng = random::from_name(&options.rng, options.seed)
+        .ok_or_else(|| Error::UnknownRng(options.rng.clone()))?;
+    chippy.set_rng(rng);
+    if let Some(addr) = options.load_address {
+        chippy.set_load_address(addr);
+    }
+    chippy.load_rom(&options.rom)?;
+    chippy.set_ipf(options.ipf);
+    chippy.set_strict(options.strict);
+
+    if let Some(movie) = &options.movie {
+        movie.start(&mut chippy)?;
+    } else if let Some(path) = &options.state_path {
+        let state = savestate::read_file(path, chippy.rom_hash())?;
+        chippy.load_state(&state)?;
+    }
+
+    if options.headless {
+        run_headless(chippy, options)
+    } else {
+        run(chippy, options)
+    }
+}
+
+/// Warns about invalid opcodes the processor skipped since the last call.
+fn report_ignored<T: InstructionSet>(chippy: &mut Processor<T>) {
+    for (pc, opcode) in chippy.take_ignored() {
+        eprintln!("ignoring invalid opcode {:04X} at 0x{:03X}", opcode, pc);
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(1);
+}
 
-    let sdl_context = match sdl2::init() {
-        Ok(sdl_context) => sdl_context,
-        Err(err) => panic!("SDL context could not initialize!  SDL_Error: {}", err),
+fn parse_args() -> Options {
+    let mut args = env::args().skip(1);
+    let mut options = Options {
+        isa: String::new(),
+        title: None,
+        colors: Vec::new(),
+        rom_keys: String::new(),
+        romdb_path: None,
+        load_address: None,
+        quirks: None,
+        ipf: DEFAULT_IPF,
+        strict: false,
+        seed: random::time_seed(),
+        rng: String::from("splitmix"),
+        audio: AudioSettings::default(),
+        wav_path: None,
+        rewind_seconds: 30,
+        debug: false,
+        keymap_path: None,
+        state_path: None,
+        record_path: None,
+        play_path: None,
+        append: false,
+        movie: None,
+        headless: false,
+        limits: RunLimits {
+            frames: 600,
+            until_pc: None,
+            until_spin: false,
+        },
+        keys_path: None,
+        screen_path: None,
+        regs_path: None,
+        filepath: String::new(),
+        rom: Vec::new(),
     };
 
-    let mut chippy = Processor::new(SuperChip);
-    let mut display = Display::new(&sdl_context);
-    let mut input = Input::new(&sdl_context);
+    // left to the ROM database if not given
+    let mut isa = None;
+    let mut ipf = None;
 
-    chippy.load(&filepath).unwrap();
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(value) => value,
+            None => usage_error(&format!("{} expects a value", arg)),
+        };
 
-    while let Ok(keypad) = input.poll() {
-        chippy.set_keypad(&keypad);
-        chippy.tick();
+        match arg.as_str() {
+            "--isa" => isa = Some(value()),
+            "--romdb" => options.romdb_path = Some(value()),
+            "--load-address" => options.load_address = Some(parse_address(&arg, Some(value()))),
+            "--quirks" => {
+                let name = value();
+                options.quirks = match Quirks::preset(&name) {
+                    Some(preset) => Some(preset),
+                    None => usage_error(&format!("unknown quirks preset '{}'", name)),
+                };
+            }
+            "--ipf" => match value().parse() {
+                Ok(value) => ipf = Some(value),
+                Err(_) => usage_error("--ipf expects a number"),
+            },
+            "--strict" => options.strict = true,
+            "--seed" => match value().parse() {
+                Ok(seed) => options.seed = seed,
+                Err(_) => usage_error("--seed expects a number"),
+            },
+            "--rng" => {
+                options.rng = value();
+                if random::from_name(&options.rng, 0).is_none() {
+                    usage_error(&format!(
+                        "unknown random number generator '{}'",
+                        options.rng
+                    ));
+                }
+            }
+            "--tone" => match value().parse() {
+                Ok(frequency) => options.audio.frequency = frequency,
+                Err(_) => usage_error("--tone expects a frequency in Hz"),
+            },
+            "--volume" => match value().parse() {
+                Ok(volume) => options.audio.volume = volume,
+                Err(_) => usage_error("--volume expects a number between 0 and 1"),
+            },
+            "--waveform" => {
+                let name = value();
+                options.audio.waveform = match Waveform::from_name(&name) {
+                    Some(waveform) => waveform,
+                    None => usage_error(&format!("unknown waveform '{}'", name)),
+                };
+            }
+            "--mute" => options.audio.muted = true,
+            "--wav" => options.wav_path = Some(value()),
+            "--debug" => options.debug = true,
+            "--rewind" => match value().parse() {
+                Ok(seconds) => options.rewind_seconds = seconds,
+                Err(_) => usage_error("--rewind expects a number of seconds"),
+            },
+            "--keymap" => options.keymap_path = Some(value()),
+            "--state" => options.state_path = Some(value()),
+            "--record" => options.record_path = Some(value()),
+            "--play" => options.play_path = Some(value()),
+            "--append" => options.append = true,
+            "--headless" => options.headless = true,
+            "--frames" => match value().parse() {
+                Ok(frames) => options.limits.frames = frames,
+                Err(_) => usage_error("--frames expects a number"),
+            },
+            "--until-pc" => options.limits.until_pc = Some(parse_address(&arg, Some(value()))),
+            "--until-spin" => options.limits.until_spin = true,
+            "--keys" => options.keys_path = Some(value()),
+            "--screen" => options.screen_path = Some(value()),
+            "--regs" => options.regs_path = Some(value()),
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option '{}'", arg)),
+            _ => options.filepath = arg,
+        }
+    }
 
-        if chippy.display_stale() {
-            display.draw(chippy.get_screen());
+    if options.filepath.is_empty() {
+        usage_error("no ROM given");
+    }
+    read_movie(&mut options);
+    read_rom(&mut options, isa, ipf);
+
+    options
+}
+
+/// Reads the ROM and takes its settings from the ROM database: the
+/// instruction set, quirks, speed and load address unless given on the
+/// command line or by a movie, and its title, colours and keys. An unknown
+/// ROM's instruction set is guessed from the opcodes it uses.
+fn read_rom(options: &mut Options, isa: Option<String>, ipf: Option<usize>) {
+    let exit = |path: &str, err: Error| -> ! {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    };
+    let mut database = RomDatabase::bundled();
+    let path = match &options.romdb_path {
+        Some(path) => Some(PathBuf::from(path)),
+        None => config_path("romdb.json").filter(|path| path.exists()),
+    };
+    if let Some(path) = path {
+        let path = path.to_string_lossy();
+        database.extend(RomDatabase::read_file(&path).unwrap_or_else(|err| exit(&path, err)));
+    }
+    let rom =
+        Rom::read(&options.filepath, &database).unwrap_or_else(|err| exit(&options.filepath, err));
+    let info = rom.info;
+
+    if options.movie.is_none() {
+        // the database's quirks and load address are for its platform, not
+        // one given instead
+        if isa.is_none() {
+            options.quirks = options.quirks.or(info.quirks);
+            options.load_address = options.load_address.or(info.load_address);
+            if rom.guessed {
+                eprintln!(
+                    "guessing instruction set '{}' from the opcodes used, --isa to choose another",
+                    info.platform.as_deref().unwrap_or_default()
+                );
+            }
         }
+        options.isa = isa.or(info.platform).unwrap_or_default();
+        options.ipf = ipf.or(info.ipf).unwrap_or(DEFAULT_IPF);
+    }
+    options.title = info.title;
+    options.colors = info.colors;
+    options.rom_keys = info.keys;
+    options.rom = rom.data;
+}
+
+/// `chip-8/<name>` in the XDG config directory.
+fn config_path(name: &str) -> Option<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("chip-8").join(name))
+}
 
-        // ensure 500Hz clock rate
-        thread::sleep(Duration::from_millis(2));
+/// Reads the movie to play back, if any, and takes its settings so the
+/// run starts out the way the recorded one did.
+fn read_movie(options: &mut Options) {
+    let path = match (&options.play_path, options.append, &options.record_path) {
+        (Some(_), true, _) => usage_error("--append plays back the --record movie, not --play"),
+        (Some(path), false, _) => path.clone(),
+        (None, true, Some(path)) => path.clone(),
+        (None, true, None) => usage_error("--append needs a movie to --record onto"),
+        (None, false, _) => return,
+    };
+    if options.state_path.is_some() {
+        usage_error("--state can't be used when playing a movie, it has its own start");
     }
+    if options.keys_path.is_some() {
+        usage_error("--keys can't be used when playing a movie");
+    }
+
+    let movie = match Movie::read_file(&path) {
+        Ok(movie) => movie,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    };
+    options.isa = movie.isa.clone();
+    options.load_address = Some(movie.load_address);
+    options.quirks = Some(movie.quirks);
+    options.ipf = movie.ipf;
+    options.rng = movie.rng.clone();
+    options.seed = movie.seed;
+    options.movie = Some(movie);
+}
+
+/// Prints the disassembly of a ROM, for `chip-8 disasm`.
+fn disasm_command() {
+    let mut args = env::args().skip(2);
+    let mut syntax = Syntax::Classic;
+    let mut origin = START_ADDRESS;
+    let mut filepath = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--octo" => syntax = Syntax::Octo,
+            "--origin" => origin = parse_address(&arg, args.next()),
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option '{}'", arg)),
+            _ => filepath = Some(arg),
+        }
+    }
+
+    let filepath = filepath.unwrap_or_else(|| usage_error("no ROM given"));
+    match fs::read(&filepath) {
+        Ok(rom) => print!("{}", disasm::disassemble(&rom, origin, syntax)),
+        Err(err) => {
+            eprintln!("could not read {}: {}", filepath, err);
+            process::exit(1);
+        }
+    }
+}
+
+/// The address given to an option like `--origin`, for programs loaded
+/// somewhere other than 0x200.
+fn parse_address(option: &str, value: Option<String>) -> u16 {
+    let value = value.unwrap_or_default();
+    match u16::from_str_radix(value.trim_start_matches("0x"), 16) {
+        Ok(addr) => addr,
+        Err(_) => usage_error(&format!("{} expects a hexadecimal address", option)),
+    }
+}
+
+/// Assembles a source file into a ROM, for `chip-8 asm`. The ROM is written
+/// next to the source unless `-o` says otherwise.
+fn asm_command() {
+    let mut args = env::args().skip(2);
+    let mut origin = START_ADDRESS;
+    let mut output = None;
+    let mut filepath = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--origin" => origin = parse_address(&arg, args.next()),
+            "-o" => {
+                output = Some(
+                    args.next()
+                        .unwrap_or_else(|| usage_error("-o expects a path")),
+                )
+            }
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option '{}'", arg)),
+            _ => filepath = Some(arg),
+        }
+    }
+
+    let filepath = filepath.unwrap_or_else(|| usage_error("no source file given"));
+    let output = output.unwrap_or_else(|| {
+        let stem = filepath
+            .rsplit_once('.')
+            .map_or(filepath.as_str(), |(stem, _)| stem);
+        format!("{}.ch8", stem)
+    });
+
+    let source = match fs::read_to_string(&filepath) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("could not read {}: {}", filepath, err);
+            process::exit(1);
+        }
+    };
+    let rom = match asm::assemble(&source, origin) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{}: {}", filepath, err);
+            process::exit(1);
+        }
+    };
+    if let Err(err) = fs::write(&output, rom) {
+        eprintln!("could not write {}: {}", output, err);
+        process::exit(1);
+    }
+}
+
+/// Runs a ROM with scripted or recorded input until a stop condition, then
+/// writes out the screen and registers, for `--headless`.
+fn run_headless<T: InstructionSet>(
+    mut chippy: Processor<T>,
+    options: &Options,
+) -> Result<(), Error> {
+    if options.record_path.is_some() {
+        usage_error("--record needs a window to take keys from");
+    }
+    let script;
+    let keys: &dyn KeySource = match (&options.movie, &options.keys_path) {
+        (Some(movie), _) => movie,
+        (None, Some(path)) => {
+            script = KeyScript::parse(&fs::read_to_string(path)?)?;
+            &script
+        }
+        (None, None) => {
+            script = KeyScript::default();
+            &script
+        }
+    };
+
+    let mut recorder = options
+        .wav_path
+        .as_ref()
+        .map(|_| WavRecorder::new(options.audio));
+    let result = headless::run(&mut chippy, &options.limits, keys, &mut |chippy| {
+        report_ignored(chippy);
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.set_pattern(chippy.get_audio_pattern(), chippy.get_pattern_rate());
+            recorder.push_frame(chippy.sound_active(), chippy.get_digitized_sound());
+        }
+    })?;
+    match result.stop {
+        Stop::Frames => eprintln!("stopped after {} frames", result.frames),
+        Stop::Pc(pc) => eprintln!("reached 0x{:03X} in frame {}", pc, result.frames),
+        Stop::Spin(pc) => eprintln!("spinning at 0x{:03X} in frame {}", pc, result.frames),
+        Stop::Halted => eprintln!("halted in frame {}", result.frames),
+    }
+
+    match &options.screen_path {
+        Some(path) if path.ends_with(".png") => fs::write(path, dump::screen_png(&chippy))?,
+        Some(path) => fs::write(path, dump::screen_text(&chippy))?,
+        None if options.regs_path.is_none() => print!("{}", dump::screen_text(&chippy)),
+        None => {}
+    }
+    if let Some(path) = &options.regs_path {
+        fs::write(path, dump::registers_json(&chippy))?;
+    }
+    if let (Some(path), Some(recorder)) = (&options.wav_path, &recorder) {
+        fs::write(path, recorder.to_wav())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run<T: InstructionSet>(_chippy: Processor<T>, _options: &Options) -> Result<(), Error> {
+    usage_error("this build has no display; rebuild with the 'sdl' feature or use --headless")
 }